@@ -0,0 +1,595 @@
+// A reader for the Chromium disk cache "blockfile" backend.
+//
+// The layout is described in Chromium's net/disk_cache/blockfile/disk_format.h. The cache
+// directory contains
+// - index: a hash table of addresses of the entries.
+// - data_0 .. data_3: block files, each storing fixed size blocks (rankings nodes, entries, keys
+//   and stream data).
+// - f_XXXXXX: external files, for data that is too big for the block files.
+//
+// Only what is needed to enumerate the entries and their keys is implemented.
+
+use super::CacheEntry;
+use anyhow::{bail, Context, Result};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const INDEX_FILE_NAME: &str = "index";
+const INDEX_MAGIC: u32 = 0xC103CAC3;
+// The header is 256 bytes followed by 112 bytes of LRU data. The hash table comes right after.
+const INDEX_HEADER_SIZE: usize = 368;
+const INDEX_TABLE_LEN_OFFSET: usize = 28;
+// Used when the table length in the header is 0.
+const DEFAULT_INDEX_TABLE_LEN: usize = 0x10000;
+
+const BLOCK_FILE_MAGIC: u32 = 0xC104CAC3;
+const BLOCK_FILE_HEADER_SIZE: usize = 8192;
+
+// Offsets in EntryStore.
+const ENTRY_NEXT_OFFSET: usize = 4;
+const ENTRY_RANKINGS_NODE_OFFSET: usize = 8;
+const ENTRY_CREATION_TIME_OFFSET: usize = 24;
+const ENTRY_KEY_LEN_OFFSET: usize = 32;
+const ENTRY_LONG_KEY_OFFSET: usize = 36;
+const ENTRY_KEY_OFFSET: usize = 96;
+
+// Offsets in RankingsNode.
+const RANKINGS_LAST_USED_OFFSET: usize = 0;
+const RANKINGS_LAST_MODIFIED_OFFSET: usize = 8;
+
+// Chromium's base::Time counts microseconds from 1601-01-01 UTC.
+const SECONDS_FROM_WINDOWS_TO_UNIX_EPOCH: u64 = 11_644_473_600;
+
+// A CacheAddr is a 32 bit value describing where the data is.
+// - bit 31: initialized flag.
+// - bits 28-30: file type.
+// - For external files, bits 0-27 are the file number.
+// - For block files, bits 24-25 are the number of blocks - 1, bits 16-23 the block file number
+//   and bits 0-15 the first block.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct CacheAddr(u32);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum FileType {
+    External,
+    Rankings,
+    Block256,
+    Block1K,
+    Block4K,
+    Other(u32),
+}
+
+impl CacheAddr {
+    fn is_initialized(&self) -> bool {
+        self.0 & 0x8000_0000 != 0
+    }
+
+    fn file_type(&self) -> FileType {
+        match (self.0 >> 28) & 0x7 {
+            0 => FileType::External,
+            1 => FileType::Rankings,
+            2 => FileType::Block256,
+            3 => FileType::Block1K,
+            4 => FileType::Block4K,
+            other => FileType::Other(other),
+        }
+    }
+
+    fn block_size(&self) -> Option<usize> {
+        match self.file_type() {
+            FileType::Rankings => Some(36),
+            FileType::Block256 => Some(256),
+            FileType::Block1K => Some(1024),
+            FileType::Block4K => Some(4096),
+            _ => None,
+        }
+    }
+
+    fn external_file_number(&self) -> u32 {
+        self.0 & 0x0FFF_FFFF
+    }
+
+    fn block_file_number(&self) -> u32 {
+        (self.0 >> 16) & 0xFF
+    }
+
+    fn start_block(&self) -> usize {
+        (self.0 & 0xFFFF) as usize
+    }
+
+    fn num_blocks(&self) -> usize {
+        (((self.0 >> 24) & 0x3) + 1) as usize
+    }
+}
+
+pub struct BlockFileCache {
+    dir: PathBuf,
+    index: Vec<u8>,
+    // Block file number (N in data_N) to its content.
+    block_files: HashMap<u32, Vec<u8>>,
+}
+
+impl BlockFileCache {
+    // Opens the cache in |dir|, e.g. webCaches/<version>/Cache/Cache_Data.
+    pub fn open(dir: &Path) -> Result<Self> {
+        let index_path = dir.join(INDEX_FILE_NAME);
+        let index = fs::read(&index_path)
+            .with_context(|| format!("Failed to read {}", index_path.display()))?;
+        if index.len() < INDEX_HEADER_SIZE {
+            bail!("{} is too small to be an index file", index_path.display());
+        }
+        if read_u32(&index, 0)? != INDEX_MAGIC {
+            bail!(
+                "{} does not have the index magic number",
+                index_path.display()
+            );
+        }
+
+        let mut block_files = HashMap::new();
+        for file in
+            fs::read_dir(dir).with_context(|| format!("Failed to read {}", dir.display()))?
+        {
+            let file = file?;
+            let filename = file.file_name();
+            let Some(number) = filename
+                .to_str()
+                .and_then(|name| name.strip_prefix("data_"))
+                .and_then(|number| number.parse::<u32>().ok())
+            else {
+                continue;
+            };
+            let content = fs::read(file.path())
+                .with_context(|| format!("Failed to read {}", file.path().display()))?;
+            if content.len() < BLOCK_FILE_HEADER_SIZE || read_u32(&content, 0)? != BLOCK_FILE_MAGIC
+            {
+                log::debug!("{} is not a block file", file.path().display());
+                continue;
+            }
+            block_files.insert(number, content);
+        }
+
+        Ok(Self {
+            dir: dir.to_path_buf(),
+            index,
+            block_files,
+        })
+    }
+
+    // Returns all the entries that could be read. Entries that are broken, e.g. partially
+    // overwritten, are skipped.
+    pub fn entries(&self) -> Result<Vec<CacheEntry>> {
+        let table_len = match read_u32(&self.index, INDEX_TABLE_LEN_OFFSET)? as usize {
+            0 => DEFAULT_INDEX_TABLE_LEN,
+            table_len => table_len,
+        };
+
+        let mut entries = vec![];
+        let mut visited = HashSet::new();
+        for bucket in 0..table_len {
+            let offset = INDEX_HEADER_SIZE + bucket * 4;
+            if offset + 4 > self.index.len() {
+                break;
+            }
+            let mut addr = CacheAddr(read_u32(&self.index, offset)?);
+            // Entries with the same hash are chained through the "next" field.
+            while addr.is_initialized() && visited.insert(addr) {
+                match self.read_entry(addr) {
+                    Ok((entry, next)) => {
+                        entries.push(entry);
+                        addr = next;
+                    }
+                    Err(e) => {
+                        log::debug!("Failed to read cache entry at {:#x}: {}", addr.0, e);
+                        break;
+                    }
+                }
+            }
+        }
+        Ok(entries)
+    }
+
+    // Returns the entry at |addr| and the address of the next entry in the same bucket.
+    fn read_entry(&self, addr: CacheAddr) -> Result<(CacheEntry, CacheAddr)> {
+        if addr.file_type() != FileType::Block256 {
+            bail!("Entry address has unexpected type {:?}", addr.file_type());
+        }
+        let store = self.read_blocks(addr)?;
+
+        let next = CacheAddr(read_u32(store, ENTRY_NEXT_OFFSET)?);
+        let creation_time =
+            chromium_time_to_system_time(read_u64(store, ENTRY_CREATION_TIME_OFFSET)?);
+        let key_len = read_u32(store, ENTRY_KEY_LEN_OFFSET)? as usize;
+        let long_key = CacheAddr(read_u32(store, ENTRY_LONG_KEY_OFFSET)?);
+        let key = if long_key.is_initialized() {
+            self.read_data(long_key, key_len)?
+        } else {
+            // Short keys are stored in the entry itself, and may continue into the following
+            // blocks of the entry.
+            store
+                .get(ENTRY_KEY_OFFSET..ENTRY_KEY_OFFSET + key_len)
+                .context("Key does not fit in the entry")?
+                .to_vec()
+        };
+
+        let rankings_node = CacheAddr(read_u32(store, ENTRY_RANKINGS_NODE_OFFSET)?);
+        let last_used = self
+            .read_blocks(rankings_node)
+            .and_then(|node| {
+                // Prefer last used, but fall back to last modified if it was never set.
+                let last_used = read_u64(node, RANKINGS_LAST_USED_OFFSET)?;
+                let last_modified = read_u64(node, RANKINGS_LAST_MODIFIED_OFFSET)?;
+                Ok(chromium_time_to_system_time(last_used.max(last_modified)))
+            })
+            .unwrap_or_else(|e| {
+                log::debug!("Failed to read rankings node: {}", e);
+                None
+            });
+
+        Ok((
+            CacheEntry {
+                key: String::from_utf8_lossy(&key).into_owned(),
+                creation_time,
+                last_used,
+            },
+            next,
+        ))
+    }
+
+    // Reads |len| bytes of the data at |addr|, which is either in a block file or an external
+    // file.
+    fn read_data(&self, addr: CacheAddr, len: usize) -> Result<Vec<u8>> {
+        if addr.file_type() == FileType::External {
+            let path = self
+                .dir
+                .join(format!("f_{:06x}", addr.external_file_number()));
+            let content =
+                fs::read(&path).with_context(|| format!("Failed to read {}", path.display()))?;
+            return Ok(content
+                .get(..len)
+                .with_context(|| format!("{} is smaller than {} bytes", path.display(), len))?
+                .to_vec());
+        }
+
+        Ok(self
+            .read_blocks(addr)?
+            .get(..len)
+            .context("Data is longer than its blocks")?
+            .to_vec())
+    }
+
+    // Returns all the blocks at |addr|.
+    fn read_blocks(&self, addr: CacheAddr) -> Result<&[u8]> {
+        if !addr.is_initialized() {
+            bail!("Address {:#x} is not initialized", addr.0);
+        }
+        let block_size = addr
+            .block_size()
+            .with_context(|| format!("Address {:#x} is not in a block file", addr.0))?;
+        let file = self
+            .block_files
+            .get(&addr.block_file_number())
+            .with_context(|| format!("Missing block file data_{}", addr.block_file_number()))?;
+        let start = BLOCK_FILE_HEADER_SIZE + addr.start_block() * block_size;
+        let end = start + addr.num_blocks() * block_size;
+        file.get(start..end).with_context(|| {
+            format!(
+                "Blocks of {:#x} are out of range of data_{}",
+                addr.0,
+                addr.block_file_number()
+            )
+        })
+    }
+}
+
+fn chromium_time_to_system_time(microseconds: u64) -> Option<SystemTime> {
+    let since_unix_epoch =
+        microseconds.checked_sub(SECONDS_FROM_WINDOWS_TO_UNIX_EPOCH * 1_000_000)?;
+    if since_unix_epoch == 0 {
+        return None;
+    }
+    UNIX_EPOCH.checked_add(Duration::from_micros(since_unix_epoch))
+}
+
+fn read_u32(content: &[u8], offset: usize) -> Result<u32> {
+    let bytes = content
+        .get(offset..offset + 4)
+        .with_context(|| format!("Cannot read 4 bytes at {}", offset))?;
+    Ok(u32::from_le_bytes(bytes.try_into()?))
+}
+
+fn read_u64(content: &[u8], offset: usize) -> Result<u64> {
+    let bytes = content
+        .get(offset..offset + 8)
+        .with_context(|| format!("Cannot read 8 bytes at {}", offset))?;
+    Ok(u64::from_le_bytes(bytes.try_into()?))
+}
+
+// Builds blockfile caches for tests.
+#[cfg(test)]
+pub mod test_util {
+    use super::*;
+
+    pub struct TestEntry<'a> {
+        pub key: &'a str,
+        // Microseconds since the Unix epoch.
+        pub creation_time: u64,
+        pub last_used: u64,
+    }
+
+    // The longest key that Chromium stores in the entry.
+    const MAX_INTERNAL_KEY_LENGTH: usize = 4 * 256 - ENTRY_KEY_OFFSET - 1;
+
+    fn to_chromium_time(unix_microseconds: u64) -> u64 {
+        unix_microseconds + SECONDS_FROM_WINDOWS_TO_UNIX_EPOCH * 1_000_000
+    }
+
+    fn block_file(block_size: usize, blocks: &[Vec<u8>]) -> Vec<u8> {
+        let mut content = vec![0; BLOCK_FILE_HEADER_SIZE];
+        content[0..4].copy_from_slice(&BLOCK_FILE_MAGIC.to_le_bytes());
+        content[12..16].copy_from_slice(&(block_size as u32).to_le_bytes());
+        for block in blocks {
+            let mut block = block.clone();
+            block.resize(block.len().div_ceil(block_size) * block_size, 0);
+            content.extend(block);
+        }
+        content
+    }
+
+    fn block_addr(file_type: u32, file_number: u32, num_blocks: u32, start_block: u32) -> u32 {
+        0x8000_0000
+            | (file_type << 28)
+            | ((num_blocks - 1) << 24)
+            | (file_number << 16)
+            | start_block
+    }
+
+    // The blocks of a block file, which are handed out in order.
+    struct Blocks {
+        file_type: u32,
+        file_number: u32,
+        block_size: usize,
+        data: Vec<Vec<u8>>,
+        next_block: u32,
+    }
+
+    impl Blocks {
+        fn new(file_type: u32, file_number: u32, block_size: usize) -> Self {
+            Self {
+                file_type,
+                file_number,
+                block_size,
+                data: vec![],
+                next_block: 0,
+            }
+        }
+
+        // Stores |data| in as many blocks as it needs, and returns their address.
+        fn push(&mut self, data: Vec<u8>) -> u32 {
+            let num_blocks = data.len().div_ceil(self.block_size).max(1) as u32;
+            let addr = block_addr(
+                self.file_type,
+                self.file_number,
+                num_blocks,
+                self.next_block,
+            );
+            self.next_block += num_blocks;
+            self.data.push(data);
+            addr
+        }
+    }
+
+    // The number of blocks of the entry for |key|. Like Chromium, keys of up to 927 bytes are
+    // stored in the entry, which takes up to 4 blocks.
+    fn entry_blocks(key: &str) -> u32 {
+        if key.len() > MAX_INTERNAL_KEY_LENGTH {
+            return 1;
+        }
+        (ENTRY_KEY_OFFSET + key.len() + 1).div_ceil(256) as u32
+    }
+
+    // Writes a blockfile cache with |entries| to |dir|. Entries are all put in the same bucket so
+    // that the chaining is exercised. The layout is the one of Chromium: the entries are in data_1,
+    // and the keys that do not fit in them are stored, with a trailing NUL, in data_1 if shorter
+    // than 1K, in data_2 if shorter than 4K, in data_3 up to 16K, and in external files otherwise.
+    pub fn write_cache(dir: &Path, entries: &[TestEntry]) -> Result<()> {
+        let mut rankings = vec![];
+        let mut data1 = Blocks::new(2, 1, 256);
+        let mut data2 = Blocks::new(3, 2, 1024);
+        let mut data3 = Blocks::new(4, 3, 4096);
+        let mut next_external_file = 1;
+
+        // The entries come first in data_1, so that their addresses are known for the chaining.
+        let entry_addrs = entries
+            .iter()
+            .map(|entry| data1.push(vec![0u8; entry_blocks(entry.key) as usize * 256]))
+            .collect::<Vec<_>>();
+
+        for (i, entry) in entries.iter().enumerate() {
+            let mut node = vec![0u8; 36];
+            node[0..8].copy_from_slice(&to_chromium_time(entry.last_used).to_le_bytes());
+            rankings.push(node);
+
+            let mut store = std::mem::take(&mut data1.data[i]);
+            let next = entry_addrs.get(i + 1).copied().unwrap_or(0);
+            store[4..8].copy_from_slice(&next.to_le_bytes());
+            store[8..12].copy_from_slice(&block_addr(1, 0, 1, i as u32).to_le_bytes());
+            store[24..32].copy_from_slice(&to_chromium_time(entry.creation_time).to_le_bytes());
+            store[32..36].copy_from_slice(&(entry.key.len() as u32).to_le_bytes());
+            let mut key = entry.key.as_bytes().to_vec();
+            key.push(0);
+            if entry.key.len() <= MAX_INTERNAL_KEY_LENGTH {
+                store[ENTRY_KEY_OFFSET..ENTRY_KEY_OFFSET + key.len()].copy_from_slice(&key);
+            } else {
+                let addr = match key.len() {
+                    ..1024 => data1.push(key),
+                    1024..4096 => data2.push(key),
+                    4096..=16384 => data3.push(key),
+                    _ => {
+                        let addr = next_external_file;
+                        fs::write(dir.join(format!("f_{:06x}", addr)), &key)?;
+                        next_external_file += 1;
+                        0x8000_0000u32 | addr
+                    }
+                };
+                store[36..40].copy_from_slice(&addr.to_le_bytes());
+            }
+            data1.data[i] = store;
+        }
+
+        let mut index = vec![0u8; INDEX_HEADER_SIZE];
+        index[0..4].copy_from_slice(&INDEX_MAGIC.to_le_bytes());
+        index[4..8].copy_from_slice(&0x30000u32.to_le_bytes());
+        let table_len = 16u32;
+        index[INDEX_TABLE_LEN_OFFSET..INDEX_TABLE_LEN_OFFSET + 4]
+            .copy_from_slice(&table_len.to_le_bytes());
+        let mut table = vec![0u8; table_len as usize * 4];
+        if let Some(first) = entry_addrs.first() {
+            table[4..8].copy_from_slice(&first.to_le_bytes());
+        }
+        index.extend(table);
+
+        fs::write(dir.join("index"), index)?;
+        fs::write(dir.join("data_0"), block_file(36, &rankings))?;
+        fs::write(dir.join("data_1"), block_file(256, &data1.data))?;
+        fs::write(dir.join("data_2"), block_file(1024, &data2.data))?;
+        fs::write(dir.join("data_3"), block_file(4096, &data3.data))?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::test_util::*;
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn cache_addr_fields() {
+        // Block file, 2 blocks in data_3 starting at block 5.
+        let addr = CacheAddr(0xB103_0005);
+        assert!(addr.is_initialized());
+        assert_eq!(FileType::Block1K, addr.file_type());
+        assert_eq!(2, addr.num_blocks());
+        assert_eq!(3, addr.block_file_number());
+        assert_eq!(5, addr.start_block());
+
+        let addr = CacheAddr(0x8000_0012);
+        assert_eq!(FileType::External, addr.file_type());
+        assert_eq!(0x12, addr.external_file_number());
+    }
+
+    #[test]
+    fn enumerate_entries() -> Result<()> {
+        let dir = tempdir()?;
+        let medium_key = format!("https://example.com/{}", "a".repeat(300));
+        let long_key = format!("https://example.com/{}", "b".repeat(2000));
+        write_cache(
+            dir.path(),
+            &[
+                TestEntry {
+                    key: "https://example.com/short",
+                    creation_time: 1_000_000,
+                    last_used: 2_000_000,
+                },
+                TestEntry {
+                    key: &medium_key,
+                    creation_time: 3_000_000,
+                    last_used: 4_000_000,
+                },
+                TestEntry {
+                    key: &long_key,
+                    creation_time: 5_000_000,
+                    last_used: 6_000_000,
+                },
+            ],
+        )?;
+
+        let cache = BlockFileCache::open(dir.path())?;
+        let entries = cache.entries()?;
+        assert_eq!(3, entries.len());
+        assert_eq!("https://example.com/short", entries[0].key);
+        assert_eq!(
+            Some(UNIX_EPOCH + Duration::from_secs(1)),
+            entries[0].creation_time
+        );
+        assert_eq!(
+            Some(UNIX_EPOCH + Duration::from_secs(2)),
+            entries[0].last_used
+        );
+        assert_eq!(medium_key, entries[1].key);
+        assert_eq!(long_key, entries[2].key);
+        assert_eq!(
+            Some(UNIX_EPOCH + Duration::from_secs(6)),
+            entries[2].last_used
+        );
+        Ok(())
+    }
+
+    // The keys are read from wherever Chromium puts them for their length.
+    #[test]
+    fn keys_of_every_length() -> Result<()> {
+        let dir = tempdir()?;
+        let keys = [
+            50,    // In one block of the entry.
+            500,   // In several blocks of the entry.
+            1000,  // In data_1, with the entries.
+            3000,  // In data_2.
+            10000, // In data_3.
+            20000, // In an external file.
+        ]
+        .map(|len| format!("https://example.com/{}", "a".repeat(len)));
+        let entries = keys
+            .iter()
+            .map(|key| TestEntry {
+                key,
+                creation_time: 1_000_000,
+                last_used: 1_000_000,
+            })
+            .collect::<Vec<_>>();
+        write_cache(dir.path(), &entries)?;
+
+        let cache = BlockFileCache::open(dir.path())?;
+        let read_keys = cache
+            .entries()?
+            .into_iter()
+            .map(|entry| entry.key)
+            .collect::<Vec<_>>();
+        assert_eq!(keys.to_vec(), read_keys);
+        Ok(())
+    }
+
+    // A key that does not fit in the entry, but is shorter than 1K, is in a BLOCK_256 of data_1.
+    #[test]
+    fn long_key_in_data_1() -> Result<()> {
+        let dir = tempdir()?;
+        let key = format!("https://example.com/{}", "a".repeat(1000));
+        write_cache(
+            dir.path(),
+            &[TestEntry {
+                key: &key,
+                creation_time: 1_000_000,
+                last_used: 1_000_000,
+            }],
+        )?;
+        assert_eq!(
+            BLOCK_FILE_HEADER_SIZE as u64,
+            fs::metadata(dir.path().join("data_2"))?.len()
+        );
+
+        let cache = BlockFileCache::open(dir.path())?;
+        let entries = cache.entries()?;
+        assert_eq!(1, entries.len());
+        assert_eq!(key, entries[0].key);
+        Ok(())
+    }
+
+    #[test]
+    fn not_a_cache() -> Result<()> {
+        let dir = tempdir()?;
+        fs::write(dir.path().join("index"), vec![0u8; INDEX_HEADER_SIZE])?;
+        assert!(BlockFileCache::open(dir.path()).is_err());
+        Ok(())
+    }
+}
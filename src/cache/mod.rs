@@ -0,0 +1,31 @@
+// Readers for the Chromium disk cache used by the games' embedded web views.
+//
+// Two backends are supported, both found in webCaches/<version>/Cache/Cache_Data.
+// - blockfile: index, data_0 .. data_3 and f_XXXXXX files.
+// - Simple Cache: one <hash>_0 file per entry, and an index-dir directory.
+
+pub mod blockfile;
+pub mod simple;
+
+use anyhow::Result;
+use std::path::Path;
+use std::time::SystemTime;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CacheEntry {
+    // The key of the entry. This is the URL of the resource, possibly prefixed with the network
+    // isolation key, e.g. "1/0/_dk_https://a.com https://a.com https://a.com/index.html".
+    pub key: String,
+    pub creation_time: Option<SystemTime>,
+    pub last_used: Option<SystemTime>,
+}
+
+// Reads the entries of the cache in |dir|, detecting which backend wrote it.
+pub fn read_entries(dir: &Path) -> Result<Vec<CacheEntry>> {
+    if simple::is_simple_cache(dir) {
+        log::debug!("{} is a Simple Cache", dir.display());
+        simple::SimpleCache::open(dir)?.entries()
+    } else {
+        blockfile::BlockFileCache::open(dir)?.entries()
+    }
+}
@@ -0,0 +1,180 @@
+// A reader for the Chromium disk cache "Simple Cache" backend.
+//
+// The layout is described in Chromium's net/disk_cache/simple/simple_entry_format.h. Each entry
+// is stored in its own files named after the hash of its key.
+// - <hash>_0: the key, followed by streams 0 and 1 (HTTP headers and body).
+// - <hash>_1: stream 2, if any.
+// - <hash>_s: sparse data, if any.
+// The index-dir directory contains an index of the entries, which is only used for detection.
+//
+// Only what is needed to enumerate the entries and their keys is implemented.
+
+use super::CacheEntry;
+use anyhow::{bail, Context, Result};
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+const INDEX_DIR_NAME: &str = "index-dir";
+const ENTRY_FILE_SUFFIX: &str = "_0";
+
+const INITIAL_MAGIC_NUMBER: u64 = 0xfcfb6d1ba7725c30;
+// SimpleFileHeader is the magic number, version, key length, key hash and padding.
+const HEADER_SIZE: usize = 24;
+const HEADER_KEY_LENGTH_OFFSET: usize = 12;
+// URLs are much shorter than this.
+const MAX_PREALLOCATED_KEY_LENGTH: usize = 64 * 1024;
+
+pub fn is_simple_cache(dir: &Path) -> bool {
+    dir.join(INDEX_DIR_NAME).is_dir()
+}
+
+pub struct SimpleCache {
+    dir: PathBuf,
+}
+
+impl SimpleCache {
+    // Opens the cache in |dir|, e.g. webCaches/<version>/Cache/Cache_Data.
+    pub fn open(dir: &Path) -> Result<Self> {
+        if !is_simple_cache(dir) {
+            bail!("{} is not a Simple Cache directory", dir.display());
+        }
+        Ok(Self {
+            dir: dir.to_path_buf(),
+        })
+    }
+
+    // Returns all the entries that could be read. Entry files that are broken are skipped.
+    pub fn entries(&self) -> Result<Vec<CacheEntry>> {
+        let mut entries = vec![];
+        for file in fs::read_dir(&self.dir)
+            .with_context(|| format!("Failed to read {}", self.dir.display()))?
+        {
+            let file = file?;
+            let filename = file.file_name();
+            let Some(hash) = filename
+                .to_str()
+                .and_then(|name| name.strip_suffix(ENTRY_FILE_SUFFIX))
+            else {
+                continue;
+            };
+            if hash.len() != 16 || !hash.chars().all(|c| c.is_ascii_hexdigit()) {
+                continue;
+            }
+
+            match read_entry(&file.path()) {
+                Ok(entry) => entries.push(entry),
+                Err(e) => log::debug!("Failed to read {}: {}", file.path().display(), e),
+            }
+        }
+        Ok(entries)
+    }
+}
+
+// Reads the key of the entry in |path|. Only the header and the key are read, since the streams
+// after them can be large.
+fn read_entry(path: &Path) -> Result<CacheEntry> {
+    let mut file = fs::File::open(path)?;
+    let mut header = [0u8; HEADER_SIZE];
+    file.read_exact(&mut header)
+        .context("File is too small for the header")?;
+    if u64::from_le_bytes(header[..8].try_into()?) != INITIAL_MAGIC_NUMBER {
+        bail!("File does not have the entry magic number");
+    }
+    let key_length = u32::from_le_bytes(
+        header[HEADER_KEY_LENGTH_OFFSET..HEADER_KEY_LENGTH_OFFSET + 4].try_into()?,
+    ) as usize;
+    // Read with take(), so that a broken key length does not allocate a huge buffer.
+    let mut key = Vec::with_capacity(key_length.min(MAX_PREALLOCATED_KEY_LENGTH));
+    (&mut file).take(key_length as u64).read_to_end(&mut key)?;
+    if key.len() != key_length {
+        bail!("Key is longer than the file");
+    }
+
+    // The entry files do not record times. The file times are the closest to the times in the
+    // index, since the files are written when the entry is used.
+    let metadata = file.metadata()?;
+    Ok(CacheEntry {
+        key: String::from_utf8_lossy(&key).into_owned(),
+        creation_time: metadata.created().ok(),
+        last_used: metadata.modified().ok(),
+    })
+}
+
+// Builds Simple Caches for tests.
+#[cfg(test)]
+pub mod test_util {
+    use super::*;
+
+    // Writes a Simple Cache with entries for |keys| to |dir|. Entry hashes are made up, since
+    // they are not checked.
+    pub fn write_cache(dir: &Path, keys: &[&str]) -> Result<()> {
+        fs::create_dir_all(dir.join(INDEX_DIR_NAME))?;
+        for (i, key) in keys.iter().enumerate() {
+            let mut content = vec![0u8; HEADER_SIZE];
+            content[..8].copy_from_slice(&INITIAL_MAGIC_NUMBER.to_le_bytes());
+            content[8..12].copy_from_slice(&5u32.to_le_bytes());
+            content[HEADER_KEY_LENGTH_OFFSET..HEADER_KEY_LENGTH_OFFSET + 4]
+                .copy_from_slice(&(key.len() as u32).to_le_bytes());
+            content.extend_from_slice(key.as_bytes());
+            // Stream data, which is not read.
+            content.extend_from_slice(&[0xAB; 64]);
+            fs::write(dir.join(format!("{:016x}_0", i + 1)), content)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::test_util::*;
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn enumerate_entries() -> Result<()> {
+        let dir = tempdir()?;
+        write_cache(
+            dir.path(),
+            &["https://example.com/a", "https://example.com/b"],
+        )?;
+        // Files that are not entry files are ignored.
+        fs::write(dir.path().join("index"), [0u8; 24])?;
+        fs::write(dir.path().join("0000000000000001_1"), [0u8; 24])?;
+
+        let cache = SimpleCache::open(dir.path())?;
+        let mut keys = cache
+            .entries()?
+            .into_iter()
+            .map(|entry| entry.key)
+            .collect::<Vec<_>>();
+        keys.sort();
+        assert_eq!(vec!["https://example.com/a", "https://example.com/b"], keys);
+        Ok(())
+    }
+
+    #[test]
+    fn broken_entry_is_skipped() -> Result<()> {
+        let dir = tempdir()?;
+        write_cache(dir.path(), &["https://example.com/a"])?;
+        fs::write(dir.path().join("00000000000000ff_0"), [0u8; 8])?;
+        // The key length is longer than the file.
+        let mut truncated = INITIAL_MAGIC_NUMBER.to_le_bytes().to_vec();
+        truncated.resize(HEADER_SIZE, 0);
+        truncated[HEADER_KEY_LENGTH_OFFSET..HEADER_KEY_LENGTH_OFFSET + 4]
+            .copy_from_slice(&u32::MAX.to_le_bytes());
+        truncated.extend_from_slice(b"https://");
+        fs::write(dir.path().join("00000000000000fe_0"), truncated)?;
+
+        let entries = SimpleCache::open(dir.path())?.entries()?;
+        assert_eq!(1, entries.len());
+        Ok(())
+    }
+
+    #[test]
+    fn not_a_simple_cache() -> Result<()> {
+        let dir = tempdir()?;
+        assert!(SimpleCache::open(dir.path()).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,503 @@
+// Finds the game install directories in the Unity logs of the games, like the official
+// PowerShell scripts do. The logs mention the data directory, e.g.
+// "Loading player data from C:/Games/Star Rail/Games/StarRail_Data/data.unity3d".
+
+use crate::games::{GameRegistry, Region};
+use std::collections::HashSet;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+const LOCAL_LOW_DIR: [&str; 2] = ["AppData", "LocalLow"];
+
+// Where games are usually installed in drive_c of a Wine prefix, and how deep the data
+// directories are searched for in them.
+const DRIVE_C_INSTALL_DIRS: [&str; 3] = ["Program Files", "Program Files (x86)", "Games"];
+const MAX_INSTALL_DIR_DEPTH: usize = 4;
+
+// Directories that contain Wine prefixes, relative to the home directory, and how deep the
+// prefixes are in them.
+const WINE_PREFIX_DIRS: [(&str, usize); 4] = [
+    (".wine", 0),
+    // Heroic puts prefixes in Prefixes/default/<game>, or Prefixes/<game> in older versions.
+    ("Games/Heroic/Prefixes", 2),
+    // Lutris puts a prefix for each game in Games/<game>.
+    ("Games", 1),
+    (".local/share/lutris/prefixes", 1),
+];
+
+// Where Steam is installed, relative to the home directory. Proton prefixes are in
+// steamapps/compatdata/<app id>/pfx of each Steam library, and the libraries are listed in
+// steamapps/libraryfolders.vdf of the Steam install.
+const STEAM_DIRS: [&str; 3] = [
+    ".steam/steam",
+    ".local/share/Steam",
+    ".var/app/com.valvesoftware.Steam/.local/share/Steam",
+];
+const STEAM_LIBRARY_FOLDERS_FILE: [&str; 2] = ["steamapps", "libraryfolders.vdf"];
+const PROTON_PREFIX_DEPTH: usize = 2;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DiscoveredInstall {
+    pub install_path: PathBuf,
+    pub game: String,
+    // The regions that the install can be. A data directory that is shared by the regions, e.g.
+    // of ZZZ, is only narrowed down when the install was found in a log of one region.
+    pub regions: Vec<Region>,
+    // The Wine prefix that the install is in, if any.
+    pub wine_prefix: Option<PathBuf>,
+    // The log that the install directory was found in. None if it was found by searching the
+    // prefix.
+    pub log_path: Option<PathBuf>,
+    // When the log was last written, i.e. about when the game was last run.
+    pub last_modified: Option<SystemTime>,
+}
+
+// Sorts |installs| so that the most recently run game comes first, and removes duplicates.
+fn sort_and_dedup(installs: &mut Vec<DiscoveredInstall>) {
+    installs.sort_by_key(|install| std::cmp::Reverse(install.last_modified));
+    let mut seen = HashSet::new();
+    installs.retain(|install| seen.insert(install.install_path.clone()));
+}
+
+// Returns the install directories found in the logs under |user_profile|, most recently run game
+// first. Only the directories that exist are returned. If |user_profile| is in a Wine prefix,
+// |wine_prefix| is used to translate the Windows paths in the logs.
+pub fn find_installs(
+    user_profile: &Path,
+    wine_prefix: Option<&Path>,
+    registry: &GameRegistry,
+) -> Vec<DiscoveredInstall> {
+    let local_low = user_profile.join(LOCAL_LOW_DIR.iter().collect::<PathBuf>());
+    let mut installs = vec![];
+    for game_type in &registry.games {
+        for log in &game_type.player_logs {
+            let log_path = local_low.join(log);
+            let Ok(content) = fs::read(&log_path) else {
+                continue;
+            };
+            let Some(windows_path) =
+                install_path_in_log(&String::from_utf8_lossy(&content), &game_type.data_dir_name)
+            else {
+                log::debug!("Found no install path in {}", log_path.display());
+                continue;
+            };
+            let install_path = windows_path_to_local(&windows_path, wine_prefix);
+            if !install_path.join(&game_type.data_dir_name).is_dir() {
+                log::debug!(
+                    "{} from {} does not exist",
+                    install_path.display(),
+                    log_path.display()
+                );
+                continue;
+            }
+            installs.push(DiscoveredInstall {
+                install_path,
+                game: game_type.name.clone(),
+                regions: vec![game_type.region],
+                wine_prefix: wine_prefix.map(Path::to_path_buf),
+                last_modified: fs::metadata(&log_path)
+                    .and_then(|metadata| metadata.modified())
+                    .ok(),
+                log_path: Some(log_path),
+            });
+        }
+    }
+    sort_and_dedup(&mut installs);
+    installs
+}
+
+// Returns the install directories in the Wine prefix at |prefix|: the ones in the logs of all the
+// users, most recently run game first, and then the ones in the usual install locations.
+pub fn find_installs_in_wine_prefix(
+    prefix: &Path,
+    registry: &GameRegistry,
+) -> Vec<DiscoveredInstall> {
+    let mut installs = fs::read_dir(prefix.join("drive_c").join("users"))
+        .into_iter()
+        .flatten()
+        .filter_map(|user| user.ok())
+        .flat_map(|user| find_installs(&user.path(), Some(prefix), registry))
+        .collect::<Vec<_>>();
+
+    let data_dir_names = registry
+        .games
+        .iter()
+        .map(|game_type| game_type.data_dir_name.as_str())
+        .collect::<HashSet<_>>();
+    for install_dir in DRIVE_C_INSTALL_DIRS {
+        let mut data_dirs = vec![];
+        find_data_dirs(
+            &prefix.join("drive_c").join(install_dir),
+            &data_dir_names,
+            MAX_INSTALL_DIR_DEPTH,
+            &mut data_dirs,
+        );
+        for data_dir in data_dirs {
+            let (Some(install_path), Some(data_dir_name)) =
+                (data_dir.parent(), data_dir.file_name())
+            else {
+                continue;
+            };
+            let game_types = registry
+                .games
+                .iter()
+                .filter(|game_type| data_dir_name == game_type.data_dir_name.as_str())
+                .collect::<Vec<_>>();
+            installs.push(DiscoveredInstall {
+                install_path: install_path.to_path_buf(),
+                game: game_types[0].name.clone(),
+                regions: game_types
+                    .iter()
+                    .map(|game_type| game_type.region)
+                    .collect(),
+                wine_prefix: Some(prefix.to_path_buf()),
+                log_path: None,
+                last_modified: None,
+            });
+        }
+    }
+    sort_and_dedup(&mut installs);
+    installs
+}
+
+// Adds the directories under |dir| that are named one of |data_dir_names| to |found|, searching
+// up to |depth| levels deep. Symlinks are not followed.
+fn find_data_dirs(
+    dir: &Path,
+    data_dir_names: &HashSet<&str>,
+    depth: usize,
+    found: &mut Vec<PathBuf>,
+) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.filter_map(|entry| entry.ok()) {
+        if !entry.file_type().is_ok_and(|file_type| file_type.is_dir()) {
+            continue;
+        }
+        if entry
+            .file_name()
+            .to_str()
+            .is_some_and(|name| data_dir_names.contains(name))
+        {
+            found.push(entry.path());
+        } else if depth > 0 {
+            find_data_dirs(&entry.path(), data_dir_names, depth - 1, found);
+        }
+    }
+}
+
+// Returns the Wine prefixes under |home|: the default one, the Proton prefixes of every Steam
+// library, and the prefixes of Lutris and Heroic.
+pub fn find_wine_prefixes(home: &Path) -> Vec<PathBuf> {
+    let mut prefixes = vec![];
+    for (dir, depth) in WINE_PREFIX_DIRS {
+        find_prefixes(&home.join(dir), depth, &mut prefixes);
+    }
+    for library in find_steam_libraries(home) {
+        find_prefixes(
+            &library.join("steamapps").join("compatdata"),
+            PROTON_PREFIX_DEPTH,
+            &mut prefixes,
+        );
+    }
+    // ~/.steam/steam is usually a symlink to ~/.local/share/Steam.
+    let mut seen = HashSet::new();
+    prefixes.retain(|prefix| seen.insert(fs::canonicalize(prefix).unwrap_or(prefix.clone())));
+    prefixes
+}
+
+// Returns the Steam libraries of the Steam installs under |home|, e.g. on other drives. The Steam
+// install itself is always one, even if libraryfolders.vdf cannot be read.
+fn find_steam_libraries(home: &Path) -> Vec<PathBuf> {
+    let mut libraries = vec![];
+    for steam_dir in STEAM_DIRS {
+        let steam_dir = home.join(steam_dir);
+        if !steam_dir.is_dir() {
+            continue;
+        }
+        libraries.push(steam_dir.clone());
+        let vdf_path = steam_dir.join(STEAM_LIBRARY_FOLDERS_FILE.iter().collect::<PathBuf>());
+        match fs::read_to_string(&vdf_path) {
+            Ok(vdf) => libraries.extend(library_paths_in_vdf(&vdf)),
+            Err(e) => log::debug!("Failed to read {}: {}", vdf_path.display(), e),
+        }
+    }
+    libraries
+}
+
+// Returns the "path" values in |vdf|, the content of libraryfolders.vdf, e.g.
+//   "libraryfolders" { "1" { "path" "/mnt/games/SteamLibrary" ... } }
+// Each key and value is quoted on one line, and backslashes in the values are escaped.
+fn library_paths_in_vdf(vdf: &str) -> Vec<PathBuf> {
+    vdf.lines()
+        .filter_map(|line| {
+            let mut tokens = line.split('"').skip(1).step_by(2);
+            match (tokens.next(), tokens.next()) {
+                (Some("path"), Some(path)) => Some(PathBuf::from(path.replace("\\\\", "\\"))),
+                _ => None,
+            }
+        })
+        .collect()
+}
+
+fn find_prefixes(dir: &Path, depth: usize, found: &mut Vec<PathBuf>) {
+    if dir.join("drive_c").is_dir() {
+        found.push(dir.to_path_buf());
+        return;
+    }
+    if depth == 0 {
+        return;
+    }
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.filter_map(|entry| entry.ok()) {
+        if entry.path().is_dir() {
+            find_prefixes(&entry.path(), depth - 1, found);
+        }
+    }
+}
+
+// Looks where the games usually are: in the user profile on Windows, and in the Wine prefixes
+// elsewhere. The prefix in $WINEPREFIX comes first.
+pub fn find_default_installs(registry: &GameRegistry) -> Vec<DiscoveredInstall> {
+    if cfg!(windows) {
+        return env::var_os("USERPROFILE")
+            .map(|user_profile| find_installs(Path::new(&user_profile), None, registry))
+            .unwrap_or_default();
+    }
+    let mut prefixes = env::var_os("WINEPREFIX")
+        .map(PathBuf::from)
+        .into_iter()
+        .collect::<Vec<_>>();
+    if let Some(home) = env::var_os("HOME") {
+        prefixes.extend(find_wine_prefixes(Path::new(&home)));
+    }
+    let mut installs = prefixes
+        .iter()
+        .flat_map(|prefix| find_installs_in_wine_prefix(prefix, registry))
+        .collect::<Vec<_>>();
+    sort_and_dedup(&mut installs);
+    installs
+}
+
+// Returns the Windows path of the install directory in |log|, i.e. the parent of the
+// |data_dir_name| directory. The last mention wins, since it is from the latest run.
+pub fn install_path_in_log(log: &str, data_dir_name: &str) -> Option<String> {
+    let data_dir = format!("/{}", data_dir_name);
+    log.lines().rev().find_map(|line| {
+        let line = line.replace('\\', "/");
+        let end = line.find(&data_dir)?;
+        // The path starts with a drive letter, e.g. "C:/". The path can have spaces, so this is
+        // the last drive letter before the data directory.
+        let start = line[..end]
+            .rmatch_indices(":/")
+            .map(|(i, _)| i)
+            .find(|&i| i > 0 && line.as_bytes()[i - 1].is_ascii_alphabetic())?
+            - 1;
+        Some(line[start..end].to_string())
+    })
+}
+
+// Translates a Windows path, e.g. "C:/Games/Genshin Impact", to a local path. In a Wine prefix,
+// the drives are symlinks in dosdevices.
+pub fn windows_path_to_local(path: &str, wine_prefix: Option<&Path>) -> PathBuf {
+    let Some(prefix) = wine_prefix else {
+        return PathBuf::from(path);
+    };
+    let Some((drive, rest)) = path.split_once(":/") else {
+        return PathBuf::from(path);
+    };
+    let drive = drive.to_ascii_lowercase();
+    let dosdevice = prefix.join("dosdevices").join(format!("{}:", drive));
+    let root = if dosdevice.exists() {
+        dosdevice
+    } else if drive == "c" {
+        prefix.join("drive_c")
+    } else if drive == "z" {
+        PathBuf::from("/")
+    } else {
+        dosdevice
+    };
+    rest.split('/')
+        .filter(|part| !part.is_empty())
+        .fold(root, |path, part| path.join(part))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn install_path_from_logs() {
+        assert_eq!(
+            Some("C:/Program Files/Star Rail/Games".to_string()),
+            install_path_in_log(
+                "[Subsystems] Discovering subsystems\n\
+                 Loading player data from C:/Program Files/Star Rail/Games/StarRail_Data/data.unity3d\n",
+                "StarRail_Data"
+            )
+        );
+        assert_eq!(
+            Some("D:/Genshin Impact/Genshin Impact Game".to_string()),
+            install_path_in_log(
+                "Warmup file E:/Old/GenshinImpact_Data/a\n\
+                 Warmup file D:/Genshin Impact/Genshin Impact Game/GenshinImpact_Data/StreamingAssets/b\n",
+                "GenshinImpact_Data"
+            )
+        );
+        assert_eq!(
+            Some("E:/ZZZ".to_string()),
+            install_path_in_log(
+                "[0.1] Path: E:\\ZZZ\\ZenlessZoneZero_Data\\Plugins",
+                "ZenlessZoneZero_Data"
+            )
+        );
+        assert_eq!(None, install_path_in_log("StarRail_Data", "StarRail_Data"));
+    }
+
+    #[test]
+    fn wine_paths() {
+        let prefix = Path::new("/home/user/.wine");
+        assert_eq!(
+            PathBuf::from("/home/user/.wine/drive_c/Program Files/Star Rail"),
+            windows_path_to_local("C:/Program Files/Star Rail", Some(prefix))
+        );
+        assert_eq!(
+            PathBuf::from("/games/Genshin Impact"),
+            windows_path_to_local("Z:/games/Genshin Impact", Some(prefix))
+        );
+        assert_eq!(
+            PathBuf::from("C:/Games"),
+            windows_path_to_local("C:/Games", None)
+        );
+    }
+
+    #[test]
+    fn find_installs_in_prefix() -> anyhow::Result<()> {
+        let prefix = tempdir()?;
+        let install_path = prefix
+            .path()
+            .join("drive_c")
+            .join("Games")
+            .join("Star Rail");
+        fs::create_dir_all(install_path.join("StarRail_Data"))?;
+        let log_dir = prefix
+            .path()
+            .join("drive_c")
+            .join("users")
+            .join("steamuser")
+            .join("AppData")
+            .join("LocalLow")
+            .join("Cognosphere")
+            .join("Star Rail");
+        fs::create_dir_all(&log_dir)?;
+        fs::write(
+            log_dir.join("Player.log"),
+            "Loading player data from C:/Games/Star Rail/StarRail_Data/data.unity3d",
+        )?;
+
+        let installs = find_installs_in_wine_prefix(prefix.path(), &GameRegistry::builtin());
+        assert_eq!(1, installs.len());
+        assert_eq!(install_path, installs[0].install_path);
+        assert_eq!(Some(log_dir.join("Player.log")), installs[0].log_path);
+        assert_eq!(vec![Region::Global], installs[0].regions);
+        Ok(())
+    }
+
+    #[test]
+    fn find_installs_in_install_dirs() -> anyhow::Result<()> {
+        let prefix = tempdir()?;
+        let install_path = prefix
+            .path()
+            .join("drive_c")
+            .join("Program Files")
+            .join("HoYoPlay")
+            .join("games")
+            .join("ZenlessZoneZero Game");
+        fs::create_dir_all(install_path.join("ZenlessZoneZero_Data").join("webCaches"))?;
+
+        let installs = find_installs_in_wine_prefix(prefix.path(), &GameRegistry::builtin());
+        assert_eq!(
+            vec![DiscoveredInstall {
+                install_path,
+                game: "Zenless Zone Zero".to_string(),
+                regions: vec![Region::Global, Region::China],
+                wine_prefix: Some(prefix.path().to_path_buf()),
+                log_path: None,
+                last_modified: None,
+            }],
+            installs
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn wine_prefixes() -> anyhow::Result<()> {
+        let home = tempdir()?;
+        let prefixes = [
+            home.path().join(".wine"),
+            home.path()
+                .join(".local/share/Steam/steamapps/compatdata/1234/pfx"),
+            home.path().join("Games/Heroic/Prefixes/default/Genshin"),
+            home.path().join("Games/genshin-impact"),
+        ];
+        for prefix in &prefixes {
+            fs::create_dir_all(prefix.join("drive_c"))?;
+        }
+        fs::create_dir_all(home.path().join("Games/not-a-prefix/drive_d"))?;
+        // A Proton prefix in a second Steam library.
+        let library = tempdir()?;
+        let library_prefix = library.path().join("steamapps/compatdata/5678/pfx");
+        fs::create_dir_all(library_prefix.join("drive_c"))?;
+        fs::write(
+            home.path()
+                .join(".local/share/Steam/steamapps/libraryfolders.vdf"),
+            format!(
+                "\"libraryfolders\"\n{{\n\t\"1\"\n\t{{\n\t\t\"path\"\t\t\"{}\"\n\t}}\n}}\n",
+                library.path().display()
+            ),
+        )?;
+
+        let mut found = find_wine_prefixes(home.path());
+        found.sort();
+        let mut expected = prefixes.to_vec();
+        expected.push(library_prefix);
+        expected.sort();
+        assert_eq!(expected, found);
+        Ok(())
+    }
+
+    #[test]
+    fn steam_library_paths() {
+        let vdf = r#"
+"libraryfolders"
+{
+	"0"
+	{
+		"path"		"/home/user/.local/share/Steam"
+		"label"		""
+		"apps"
+		{
+			"228980"		"123"
+		}
+	}
+	"1"
+	{
+		"path"		"D:\\SteamLibrary"
+	}
+}
+"#;
+        assert_eq!(
+            vec![
+                PathBuf::from("/home/user/.local/share/Steam"),
+                PathBuf::from("D:\\SteamLibrary")
+            ],
+            library_paths_in_vdf(vdf)
+        );
+    }
+}
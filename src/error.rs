@@ -0,0 +1,97 @@
+// The errors of finding and validating gacha URLs. Each variant is a failure that the user can
+// fix in a different way, so callers can tell them apart.
+
+use std::path::PathBuf;
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    // None of the known game data directories is in the install directory.
+    #[error("Failed to find data directory in {0}")]
+    DataDirNotFound(PathBuf),
+    // The game has not created its web cache yet.
+    #[error("{0} is not a directory")]
+    WebCacheDirNotFound(PathBuf),
+    #[error("Failed to find any versioned directories in {0}")]
+    NoVersionedCacheDir(PathBuf),
+    // None of the versioned directories has a data_2 file or a Simple Cache directory.
+    #[error("Failed to find data_2 file or a Simple Cache directory in {0}")]
+    NoCacheData(PathBuf),
+    // The cache does not have any gacha URLs, e.g. the gacha logs were not opened in game.
+    #[error("Found no gacha URLs")]
+    NoUrlsFound,
+    // All the URLs failed the validation. The errors are in the order that the URLs were tried,
+    // i.e. newest first.
+    #[error("Failed to find a working gacha URL. Check the gacha logs in game first.")]
+    NoWorkingUrl(Vec<Error>),
+    // The API rejected the URL. |message| is the message in the response.
+    // retcode -101: The URL is too old. Opening the gacha history in game makes a new one.
+    #[error("The authkey has expired: {message}")]
+    AuthkeyExpired { message: String },
+    // retcode -100: The URL is broken, e.g. it was cut off. Opening the gacha history in game
+    // makes a new one.
+    #[error("The authkey is not valid: {message}")]
+    AuthkeyInvalid { message: String },
+    // retcode -110: Too many requests. Retrying later works.
+    #[error("Too many requests: {message}")]
+    RateLimited { message: String },
+    // retcode -111: The URL was sent to the API of another game or region.
+    #[error("The URL is for another game or region: {message}")]
+    WrongGame { message: String },
+    // Any other non-zero retcode.
+    #[error("The API returned retcode {retcode}: {message}")]
+    Api { retcode: i64, message: String },
+    #[error("{url} is not a valid URL: {reason}")]
+    InvalidUrl { url: String, reason: String },
+    #[error("Unexpected response from {url}: {reason}")]
+    InvalidResponse { url: String, reason: String },
+    #[error("Network error: {0}")]
+    Network(#[from] reqwest::Error),
+}
+
+// What the user can do to fix an error.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Remedy {
+    // The path is not a game install directory.
+    CheckInstallPath,
+    // Opening the gacha history in game caches a new URL.
+    OpenGachaHistory,
+    // Waiting a bit and retrying.
+    RetryLater,
+    // The game definitions do not match the game or the region of the URL.
+    CheckRegion,
+    CheckNetwork,
+}
+
+impl Error {
+    pub fn remedy(&self) -> Option<Remedy> {
+        match self {
+            Error::DataDirNotFound(_) => Some(Remedy::CheckInstallPath),
+            Error::WebCacheDirNotFound(_)
+            | Error::NoVersionedCacheDir(_)
+            | Error::NoCacheData(_)
+            | Error::NoUrlsFound
+            | Error::AuthkeyExpired { .. }
+            | Error::AuthkeyInvalid { .. } => Some(Remedy::OpenGachaHistory),
+            // The newest URL is the most relevant one.
+            Error::NoWorkingUrl(errors) => errors.first().and_then(Error::remedy),
+            Error::RateLimited { .. } => Some(Remedy::RetryLater),
+            Error::WrongGame { .. } => Some(Remedy::CheckRegion),
+            Error::Network(_) => Some(Remedy::CheckNetwork),
+            Error::Api { .. } | Error::InvalidUrl { .. } | Error::InvalidResponse { .. } => None,
+        }
+    }
+
+    // The retcode that the API returned, if the error came from the API.
+    pub fn retcode(&self) -> Option<i64> {
+        match self {
+            Error::AuthkeyInvalid { .. } => Some(-100),
+            Error::AuthkeyExpired { .. } => Some(-101),
+            Error::RateLimited { .. } => Some(-110),
+            Error::WrongGame { .. } => Some(-111),
+            Error::Api { retcode, .. } => Some(*retcode),
+            _ => None,
+        }
+    }
+}
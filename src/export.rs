@@ -0,0 +1,125 @@
+// Exports the fetched gacha history as spreadsheets, one row per pull.
+
+use crate::history::{GachaHistory, GachaRecord};
+use anyhow::{Context, Result};
+use std::io::Write;
+
+const HEADER: [&str; 7] = [
+    "time",
+    "banner",
+    "gacha_type",
+    "name",
+    "item_type",
+    "rank",
+    "id",
+];
+
+fn row<'a>(banner_name: &'a str, record: &'a GachaRecord) -> [&'a str; 7] {
+    [
+        &record.time,
+        banner_name,
+        &record.gacha_type,
+        &record.name,
+        &record.item_type,
+        &record.rank_type,
+        &record.id,
+    ]
+}
+
+// Writes all the pulls of |history| as CSV, banner by banner, oldest first.
+pub fn write_csv(history: &GachaHistory, writer: impl Write) -> Result<()> {
+    let mut writer = csv::Writer::from_writer(writer);
+    writer.write_record(HEADER)?;
+    for banner in &history.banners {
+        for record in banner.records.iter().rev() {
+            writer.write_record(row(&banner.name, record))?;
+        }
+    }
+    writer.flush().context("Failed to write CSV")?;
+    Ok(())
+}
+
+// Writes the pulls of |history| to an XLSX file at |path|, with a sheet for each banner. The
+// pulls are oldest first.
+#[cfg(feature = "xlsx")]
+pub fn write_xlsx(history: &GachaHistory, path: &std::path::Path) -> Result<()> {
+    use rust_xlsxwriter::{Format, Workbook};
+
+    let mut workbook = Workbook::new();
+    let header_format = Format::new().set_bold();
+    for banner in &history.banners {
+        let worksheet = workbook.add_worksheet();
+        // Sheet names cannot be longer than 31 characters.
+        let sheet_name = banner.name.chars().take(31).collect::<String>();
+        worksheet
+            .set_name(&sheet_name)
+            .with_context(|| format!("Failed to name sheet {}", sheet_name))?;
+        worksheet.write_row_with_format(0, 0, HEADER, &header_format)?;
+        worksheet.set_freeze_panes(1, 0)?;
+        for (i, record) in banner.records.iter().rev().enumerate() {
+            let row_number = i as u32 + 1;
+            worksheet.write_row(row_number, 0, row(&banner.name, record))?;
+            // Write the rank as a number, so that it can be used in formulas.
+            if let Ok(rank) = record.rank_type.parse::<u32>() {
+                worksheet.write_number(row_number, 5, rank)?;
+            }
+        }
+    }
+    workbook
+        .save(path)
+        .with_context(|| format!("Failed to save {}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::history::test_util::{self, banner, record};
+    use crate::history::BannerHistory;
+
+    fn character(id: &str, name: &str) -> GachaRecord {
+        GachaRecord {
+            time: format!("2024-01-0{} 00:00:00", id),
+            name: name.to_string(),
+            item_type: "Character".to_string(),
+            rank_type: "5".to_string(),
+            ..record(id, "301")
+        }
+    }
+
+    fn history() -> GachaHistory {
+        let records = vec![
+            character("2", "Hu Tao"),
+            character("1", "Zhongli, Vago Mundo"),
+        ];
+        test_util::history(
+            "hk4e_global",
+            vec![BannerHistory {
+                name: "Character Event Wish".to_string(),
+                ..banner("301", records)
+            }],
+        )
+    }
+
+    #[test]
+    fn csv_rows_oldest_first() -> Result<()> {
+        let mut output = vec![];
+        write_csv(&history(), &mut output)?;
+        assert_eq!(
+            "time,banner,gacha_type,name,item_type,rank,id\n\
+             2024-01-01 00:00:00,Character Event Wish,301,\"Zhongli, Vago Mundo\",Character,5,1\n\
+             2024-01-02 00:00:00,Character Event Wish,301,Hu Tao,Character,5,2\n",
+            String::from_utf8(output)?
+        );
+        Ok(())
+    }
+
+    #[cfg(feature = "xlsx")]
+    #[test]
+    fn xlsx_is_written() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("pulls.xlsx");
+        write_xlsx(&history(), &path)?;
+        assert!(std::fs::metadata(&path)?.len() > 0);
+        Ok(())
+    }
+}
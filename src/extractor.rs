@@ -0,0 +1,1033 @@
+// Finds gacha URLs in the web caches of a game install, and picks the newest one that works.
+
+use crate::cache;
+use crate::error::{Error, Result};
+use crate::games::{GameRegistry, GameTypeData};
+use crate::validate::{self, HttpClient, UrlValidator};
+use anyhow::Context;
+use bstr::ByteSlice;
+use reqwest::Url;
+use std::cmp::Ordering;
+use std::collections::HashSet;
+use std::fs;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering as AtomicOrdering};
+use std::sync::mpsc;
+use std::thread;
+use std::time::SystemTime;
+
+const MAX_URL_LENGTH: usize = 2048;
+
+// The maximum number of URLs that are validated at the same time, unless set with
+// PullExtractor::with_parallelism(). Kept low, so that the API does not rate limit the requests.
+pub const DEFAULT_PARALLELISM: usize = 4;
+
+// Genshin's version folders have 4 numbers.
+// The field names are arbitrary names that I gave, not from any source.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Version {
+    major: u32,
+    minor: u32,
+    patch: u32,
+    other: u32,
+}
+
+impl Ord for Version {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.major
+            .cmp(&other.major)
+            .then_with(|| self.minor.cmp(&other.minor))
+            .then_with(|| self.patch.cmp(&other.patch))
+            .then_with(|| self.other.cmp(&other.other))
+    }
+}
+
+impl PartialOrd for Version {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+struct VersionedDirectory {
+    path: PathBuf,
+    version: Version,
+}
+
+// A URL found in the cache, along with the game type it was found for.
+struct Candidate<'a> {
+    game_type: &'a GameTypeData,
+    url: String,
+    // When the cache entry was last used or created. None if the URL was not found in a cache
+    // entry, e.g. when scanning the raw data_2 file.
+    timestamp: Option<SystemTime>,
+    // The versioned directory in webCaches that the URL was found in.
+    source_dir: PathBuf,
+    // The cache directory (Cache/Cache_Data) that the URL was found in.
+    cache_dir: PathBuf,
+}
+
+// A gacha URL that passed the validation.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExtractedUrl {
+    pub url: String,
+    // See Candidate.
+    pub timestamp: Option<SystemTime>,
+    pub source_dir: PathBuf,
+    pub cache_dir: PathBuf,
+    // The game type that the URL was found for.
+    pub game_type: GameTypeData,
+    // Whether the URL passed the validation. False in offline mode, where the URL may have
+    // expired.
+    pub verified: bool,
+}
+
+// A URL found in the cache, along with the result of its validation.
+#[derive(Debug)]
+pub struct CheckedUrl {
+    // |extracted.verified| is true if the URL passed the validation.
+    pub extracted: ExtractedUrl,
+    // Why the URL failed the validation. None if it passed.
+    pub error: Option<Error>,
+}
+
+// Where the URLs are searched for.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Source {
+    // The game install directory. The web caches are in its data directory.
+    InstallDir(PathBuf),
+    // A single data_2 file, e.g. one that was copied from another machine.
+    Data2File(PathBuf),
+}
+
+pub struct PullExtractor {
+    source: Source,
+    // Global and CN clients of ZZZ and Star Rail use the same data directory name, so every game
+    // type with a matching data directory is kept. The URLs in the cache decide which one applies.
+    game_types: Vec<GameTypeData>,
+    // Used to validate the URLs.
+    client: Box<dyn HttpClient>,
+    // The maximum number of URLs that are validated at the same time.
+    parallelism: usize,
+}
+
+impl PullExtractor {
+    pub fn new(install_path: &Path) -> Result<Self> {
+        Self::with_registry(install_path, GameRegistry::builtin())
+    }
+
+    pub fn with_registry(install_path: &Path, registry: GameRegistry) -> Result<Self> {
+        let game_types = registry.games;
+
+        // Only the first data directory found is used, along with all the regions that share it.
+        let data_dir_name = game_types
+            .iter()
+            .map(|game_type| game_type.data_dir_name.clone())
+            .find(|data_dir_name| install_path.join(data_dir_name).is_dir())
+            .ok_or_else(|| Error::DataDirNotFound(install_path.to_path_buf()))?;
+        let game_types = game_types
+            .into_iter()
+            .filter(|game_type| game_type.data_dir_name == data_dir_name)
+            .collect();
+
+        Ok(Self {
+            source: Source::InstallDir(install_path.to_path_buf()),
+            game_types,
+            client: Box::new(reqwest::blocking::Client::new()),
+            parallelism: DEFAULT_PARALLELISM,
+        })
+    }
+
+    // Searches the data_2 file at |data2_path| instead of the web caches of an install. Since
+    // there is no data directory to tell the game, the URLs of every game type in |registry| are
+    // searched for.
+    pub fn from_data2_file(data2_path: &Path, registry: GameRegistry) -> Self {
+        Self {
+            source: Source::Data2File(data2_path.to_path_buf()),
+            game_types: registry.games,
+            client: Box::new(reqwest::blocking::Client::new()),
+            parallelism: DEFAULT_PARALLELISM,
+        }
+    }
+
+    // Replaces the HTTP client that is used to validate the URLs.
+    pub fn with_http_client(mut self, client: impl HttpClient + 'static) -> Self {
+        self.client = Box::new(client);
+        self
+    }
+
+    // Sets the maximum number of URLs that are validated at the same time. 1 validates them one
+    // by one.
+    pub fn with_parallelism(mut self, parallelism: usize) -> Self {
+        self.parallelism = parallelism.max(1);
+        self
+    }
+
+    // The game types that the URLs are searched for.
+    pub fn game_types(&self) -> &[GameTypeData] {
+        &self.game_types
+    }
+
+    pub fn source(&self) -> &Source {
+        &self.source
+    }
+
+    // The HTTP client that validates the URLs, e.g. to fetch the history with the same one.
+    pub fn http_client(&self) -> &dyn HttpClient {
+        self.client.as_ref()
+    }
+
+    // The webCaches directory that the URLs are searched in. None when searching a data_2 file.
+    pub fn web_cache_dir(&self) -> Option<PathBuf> {
+        const WEB_CACHE_DIR_NAME: &str = "webCaches";
+        match &self.source {
+            // All game types share the same data directory. See with_registry().
+            Source::InstallDir(install_path) => Some(
+                install_path
+                    .join(&self.game_types[0].data_dir_name)
+                    .join(WEB_CACHE_DIR_NAME),
+            ),
+            Source::Data2File(_) => None,
+        }
+    }
+
+    pub fn extract_url(&self) -> Result<ExtractedUrl> {
+        self.validate_candidates(self.find_ranked_candidates()?)
+    }
+
+    // Returns all the URLs without validating them, best first. This works without network, but
+    // the URLs may have expired.
+    pub fn extract_unverified_urls(&self) -> Result<Vec<ExtractedUrl>> {
+        self.find_ranked_candidates()?
+            .into_iter()
+            .map(|candidate| {
+                Ok(ExtractedUrl {
+                    url: candidate.game_type.validation.normalize(&candidate.url)?,
+                    timestamp: candidate.timestamp,
+                    source_dir: candidate.source_dir,
+                    cache_dir: candidate.cache_dir,
+                    game_type: candidate.game_type.clone(),
+                    verified: false,
+                })
+            })
+            .collect()
+    }
+
+    // Validates all the URLs, and returns them best first along with the results. Unlike
+    // extract_url(), this does not stop at the first URL that works.
+    pub fn check_all_urls(&self) -> Result<Vec<CheckedUrl>> {
+        Ok(self.check_candidates(self.find_ranked_candidates()?))
+    }
+
+    // Like check_all_urls(), but skips the URLs whose authkeys are in |checked_authkeys|, and adds
+    // the authkeys of the URLs whose results cannot change: the ones that work, and the ones with
+    // an expired or invalid authkey or of another game. Watching the cache uses this to check only
+    // the new URLs. The other failures, e.g. of the network or of the server, are checked again.
+    pub fn check_new_urls(
+        &self,
+        checked_authkeys: &mut HashSet<String>,
+    ) -> Result<Vec<CheckedUrl>> {
+        let mut candidates = self.find_ranked_candidates()?;
+        candidates.retain(|candidate| !checked_authkeys.contains(&authkey(&candidate.url)));
+        let checked = self.check_candidates(candidates);
+        for checked in &checked {
+            if matches!(
+                checked.error,
+                None | Some(
+                    Error::AuthkeyExpired { .. }
+                        | Error::AuthkeyInvalid { .. }
+                        | Error::WrongGame { .. }
+                )
+            ) {
+                checked_authkeys.insert(authkey(&checked.extracted.url));
+            }
+        }
+        Ok(checked)
+    }
+
+    // Validates |candidates| concurrently, and returns them in the same order along with the
+    // results.
+    fn check_candidates(&self, candidates: Vec<Candidate<'_>>) -> Vec<CheckedUrl> {
+        let urls = candidates
+            .iter()
+            .map(|candidate| (&candidate.game_type.validation, candidate.url.as_str()))
+            .collect::<Vec<_>>();
+        let results = validate::validate_all(self.client.as_ref(), &urls, self.parallelism);
+        candidates
+            .into_iter()
+            .zip(results)
+            .map(|(candidate, result)| {
+                let validation = &candidate.game_type.validation;
+                let (url, error) = match result {
+                    Ok(url) => (url, None),
+                    Err(e) => (
+                        validation
+                            .normalize(&candidate.url)
+                            .unwrap_or_else(|_| candidate.url.clone()),
+                        Some(e),
+                    ),
+                };
+                CheckedUrl {
+                    extracted: ExtractedUrl {
+                        url,
+                        timestamp: candidate.timestamp,
+                        source_dir: candidate.source_dir,
+                        cache_dir: candidate.cache_dir,
+                        game_type: candidate.game_type.clone(),
+                        verified: error.is_none(),
+                    },
+                    error,
+                }
+            })
+            .collect()
+    }
+
+    // Returns the URLs in the cache, newest first, with one URL per authkey.
+    fn find_ranked_candidates(&self) -> Result<Vec<Candidate<'_>>> {
+        let mut candidates = match &self.source {
+            Source::InstallDir(_) => self.find_candidates_in_web_caches()?,
+            Source::Data2File(data2_path) => self.find_candidates_in_data2_file(data2_path)?,
+        };
+        if candidates.is_empty() {
+            return Err(Error::NoUrlsFound);
+        }
+
+        // Older URLs are likely to have expired authkeys, so try the newest first. The sort is
+        // stable, so candidates without a timestamp stay in file order, after the others.
+        candidates.sort_by_key(|candidate| std::cmp::Reverse(candidate.timestamp));
+        // The cache often has many copies of the same URL, e.g. with different page numbers. Only
+        // the newest one of each authkey needs to be tried.
+        let mut seen_authkeys = HashSet::new();
+        candidates.retain(|candidate| seen_authkeys.insert(authkey(&candidate.url)));
+        Ok(candidates)
+    }
+
+    fn find_candidates_in_web_caches(&self) -> Result<Vec<Candidate<'_>>> {
+        let web_cache_dir = self
+            .web_cache_dir()
+            .expect("Install directory has a web cache directory");
+        if !web_cache_dir.is_dir() {
+            return Err(Error::WebCacheDirNotFound(web_cache_dir));
+        }
+
+        let cache_dirs = get_cache_dirs(&web_cache_dir)?;
+
+        // The newest versioned directory is often empty right after a game update, while a
+        // working URL is still in the previous version's cache. So candidates from all of them
+        // are merged, newest directory first.
+        let mut candidates = vec![];
+        for cache_dir in &cache_dirs {
+            match self.find_candidates(cache_dir) {
+                Ok(found) => candidates.extend(found),
+                Err(e) => log::debug!("Failed to find URLs in {}: {}", cache_dir.path.display(), e),
+            }
+        }
+        Ok(candidates)
+    }
+
+    fn find_candidates_in_data2_file(&self, data2_path: &Path) -> Result<Vec<Candidate<'_>>> {
+        if !data2_path.is_file() {
+            return Err(Error::NoCacheData(data2_path.to_path_buf()));
+        }
+        // The file may be in a whole cache directory, whose entries have the timestamps.
+        let cache_dir = data2_path.parent().unwrap_or(Path::new("."));
+        let found = match self.find_urls_in_cache_entries(cache_dir, cache_dir) {
+            Ok(candidates) if !candidates.is_empty() => Ok(candidates),
+            Ok(_) => {
+                log::debug!(
+                    "Found no URLs in cache entries, scanning {} instead",
+                    data2_path.display()
+                );
+                self.find_urls_in_data2(data2_path, cache_dir)
+            }
+            Err(e) => {
+                log::debug!(
+                    "Failed to read cache entries, scanning {} instead: {}",
+                    data2_path.display(),
+                    e
+                );
+                self.find_urls_in_data2(data2_path, cache_dir)
+            }
+        };
+        found.map_err(|e| {
+            log::debug!("Failed to find URLs in {}: {}", data2_path.display(), e);
+            Error::NoCacheData(data2_path.to_path_buf())
+        })
+    }
+
+    // Validates |candidates| concurrently, and returns the first one that works. Since
+    // |candidates| are newest first, a working candidate is chosen once all the ones before it
+    // have failed. The rest are not validated then, but the requests that are already in flight,
+    // at most one per thread, are still waited for.
+    fn validate_candidates(&self, candidates: Vec<Candidate<'_>>) -> Result<ExtractedUrl> {
+        let next_index = AtomicUsize::new(0);
+        // The index of the first candidate that is known to work. The ones after it do not need
+        // to be validated.
+        let first_working = AtomicUsize::new(usize::MAX);
+        let finished = AtomicBool::new(false);
+        let (sender, receiver) = mpsc::channel();
+
+        thread::scope(|scope| {
+            for _ in 0..self.parallelism.min(candidates.len()) {
+                let sender = sender.clone();
+                let (candidates, next_index, first_working, finished) =
+                    (&candidates, &next_index, &first_working, &finished);
+                scope.spawn(move || {
+                    while !finished.load(AtomicOrdering::SeqCst) {
+                        let index = next_index.fetch_add(1, AtomicOrdering::SeqCst);
+                        if index > first_working.load(AtomicOrdering::SeqCst) {
+                            break;
+                        }
+                        let Some(candidate) = candidates.get(index) else {
+                            break;
+                        };
+                        let result = candidate
+                            .game_type
+                            .validation
+                            .validate(self.client.as_ref(), &candidate.url);
+                        match result {
+                            Ok(_) => {
+                                first_working.fetch_min(index, AtomicOrdering::SeqCst);
+                            }
+                            Err(Error::RateLimited { .. }) => {
+                                finished.store(true, AtomicOrdering::SeqCst);
+                            }
+                            Err(_) => {}
+                        }
+                        if sender.send((index, result)).is_err() {
+                            break;
+                        }
+                    }
+                });
+            }
+            drop(sender);
+
+            let mut results = candidates.iter().map(|_| None).collect::<Vec<_>>();
+            let mut errors = vec![];
+            for (index, result) in receiver {
+                results[index] = Some(result);
+                // Go through the results in order, up to the first one that is not known yet.
+                while let Some(result) = results.get_mut(errors.len()).and_then(Option::take) {
+                    let candidate = &candidates[errors.len()];
+                    match result {
+                        Ok(url) => {
+                            finished.store(true, AtomicOrdering::SeqCst);
+                            return Ok(ExtractedUrl {
+                                url,
+                                timestamp: candidate.timestamp,
+                                source_dir: candidate.source_dir.clone(),
+                                cache_dir: candidate.cache_dir.clone(),
+                                game_type: candidate.game_type.clone(),
+                                verified: true,
+                            });
+                        }
+                        // The other URLs would be rate limited too.
+                        Err(e @ Error::RateLimited { .. }) => {
+                            finished.store(true, AtomicOrdering::SeqCst);
+                            return Err(e);
+                        }
+                        Err(e) => {
+                            log::debug!("Testing {} returned an error: {}", candidate.url, e);
+                            errors.push(e);
+                        }
+                    }
+                }
+            }
+            Err(Error::NoWorkingUrl(errors))
+        })
+    }
+
+    fn find_candidates(&self, cache_dir: &CacheDir) -> anyhow::Result<Vec<Candidate<'_>>> {
+        // Prefer reading the cache entries, since the URLs are the keys of the entries. Scanning
+        // the raw data_2 file is kept as a fallback for blockfile caches that cannot be parsed,
+        // and for URLs whose entries are no longer in the index, e.g. a stale or partial index.
+        let data2_path = cache_dir.path.join(DATA2_FILE_NAME);
+        match self.find_urls_in_cache_entries(&cache_dir.path, &cache_dir.versioned_dir) {
+            Ok(candidates) if !candidates.is_empty() || !data2_path.is_file() => Ok(candidates),
+            Ok(_) => {
+                log::debug!("Found no URLs in cache entries, scanning data_2 instead");
+                self.find_urls_in_data2(&data2_path, &cache_dir.versioned_dir)
+            }
+            Err(e) => {
+                if !data2_path.is_file() {
+                    return Err(e);
+                }
+                log::debug!(
+                    "Failed to read cache entries, scanning data_2 instead: {}",
+                    e
+                );
+                self.find_urls_in_data2(&data2_path, &cache_dir.versioned_dir)
+            }
+        }
+    }
+
+    fn find_urls_in_cache_entries(
+        &self,
+        cache_dir: &Path,
+        source_dir: &Path,
+    ) -> anyhow::Result<Vec<Candidate<'_>>> {
+        let entries = cache::read_entries(cache_dir)?;
+        let mut candidates = vec![];
+        for entry in entries {
+            for game_type in &self.game_types {
+                // Most keys do not contain the markers, so errors are expected here.
+                let Ok(urls) = find_gacha_urls_in_slice(
+                    entry.key.as_bytes(),
+                    &game_type.marker,
+                    &game_type.url_start,
+                    &game_type.url_end,
+                ) else {
+                    continue;
+                };
+                for url in urls {
+                    log::debug!(
+                        "Found {} in cache entry created at {:?}, last used at {:?}",
+                        url,
+                        entry.creation_time,
+                        entry.last_used
+                    );
+                    candidates.push(Candidate {
+                        game_type,
+                        url,
+                        timestamp: entry.last_used.max(entry.creation_time),
+                        source_dir: source_dir.to_path_buf(),
+                        cache_dir: cache_dir.to_path_buf(),
+                    });
+                }
+            }
+        }
+        Ok(candidates)
+    }
+
+    fn find_urls_in_data2(
+        &self,
+        data2_path: &Path,
+        source_dir: &Path,
+    ) -> anyhow::Result<Vec<Candidate<'_>>> {
+        let content = fs::read(data2_path).context("Failed to read data_2 file")?;
+        let cache_dir = data2_path.parent().unwrap_or(Path::new("."));
+        let mut candidates = vec![];
+        for game_type in &self.game_types {
+            let result = find_gacha_urls_in_slice(
+                &content,
+                &game_type.marker,
+                &game_type.url_start,
+                &game_type.url_end,
+            );
+            match result {
+                Ok(urls) => candidates.extend(urls.into_iter().map(|url| Candidate {
+                    game_type,
+                    url,
+                    timestamp: None,
+                    source_dir: source_dir.to_path_buf(),
+                    cache_dir: cache_dir.to_path_buf(),
+                })),
+                Err(e) => {
+                    log::debug!(
+                        "Searching for {} ({:?}) URLs returned an error: {}",
+                        game_type.name,
+                        game_type.region,
+                        e
+                    );
+                    continue;
+                }
+            }
+        }
+        Ok(candidates)
+    }
+}
+
+// Returns the authkey of |url|, or the whole URL if it does not have one.
+fn authkey(url: &str) -> String {
+    Url::parse(url)
+        .ok()
+        .and_then(|url| {
+            url.query_pairs()
+                .find(|(key, _)| key == "authkey")
+                .map(|(_, value)| value.into_owned())
+        })
+        .unwrap_or_else(|| url.to_string())
+}
+
+// Whether |filename| is the name of a versioned directory in webCaches, e.g. "2.5.0.0".
+pub(crate) fn is_versioned_dir_name(filename: &str) -> bool {
+    filename_to_version(filename).is_some()
+}
+
+fn filename_to_version(filename: &str) -> Option<Version> {
+    let parts = filename.split('.').collect::<Vec<&str>>();
+    if parts.len() != 4 {
+        return None;
+    }
+    let major = parts[0].parse::<u32>().ok()?;
+    let minor = parts[1].parse::<u32>().ok()?;
+    let patch = parts[2].parse::<u32>().ok()?;
+    let other = parts[3].parse::<u32>().ok()?;
+    Some(Version {
+        major,
+        minor,
+        patch,
+        other,
+    })
+}
+
+fn collect_versioned_directories(path: &Path) -> Vec<VersionedDirectory> {
+    let files = fs::read_dir(path);
+    if files.is_err() {
+        return vec![];
+    }
+
+    let files = files.unwrap();
+
+    files
+        .filter_map(|file| {
+            let file = file.ok()?;
+            let filename = file.file_name();
+            let filename_str = filename.to_str()?;
+
+            let version = filename_to_version(filename_str)?;
+            let versioned_directory = VersionedDirectory {
+                path: path.join(filename_str).to_path_buf(),
+                version,
+            };
+            Some(versioned_directory)
+        })
+        .collect()
+}
+
+const RELATIVE_PATH_TO_CACHE_DATA: &[&str] = &["Cache", "Cache_Data"];
+const DATA2_FILE_NAME: &str = "data_2";
+
+// A cache directory (Cache/Cache_Data) and the versioned directory that it is in.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CacheDir {
+    pub versioned_dir: PathBuf,
+    pub path: PathBuf,
+}
+
+// Returns the cache directories in all the versioned directories, newest version first. Only the
+// ones with a data_2 file (blockfile backend) or an index-dir (Simple Cache backend) are returned.
+pub fn get_cache_dirs(web_cache_dir: &Path) -> Result<Vec<CacheDir>> {
+    let mut versioned_dirs = collect_versioned_directories(web_cache_dir);
+
+    if versioned_dirs.is_empty() {
+        return Err(Error::NoVersionedCacheDir(web_cache_dir.to_path_buf()));
+    }
+
+    // Note that this is descending order, i.e. the biggest version is at the front.
+    // The latest gacha info is most likely in the latest webcache dir.
+    versioned_dirs.sort_by_key(|dir| std::cmp::Reverse(dir.version));
+
+    let cache_dirs = versioned_dirs
+        .into_iter()
+        .filter_map(|versioned_dir| {
+            let cache_data_path = versioned_dir
+                .path
+                .join(RELATIVE_PATH_TO_CACHE_DATA.iter().collect::<PathBuf>());
+            if !cache_data_path.join(DATA2_FILE_NAME).is_file()
+                && !cache::simple::is_simple_cache(&cache_data_path)
+            {
+                return None;
+            }
+            Some(CacheDir {
+                versioned_dir: versioned_dir.path,
+                path: cache_data_path,
+            })
+        })
+        .collect::<Vec<_>>();
+    if cache_dirs.is_empty() {
+        return Err(Error::NoCacheData(web_cache_dir.to_path_buf()));
+    }
+    Ok(cache_dirs)
+}
+
+pub fn find_gacha_urls_in_slice(
+    content: &[u8],
+    marker: &str,
+    url_start: &str,
+    end_marker: &str,
+) -> anyhow::Result<Vec<String>> {
+    content
+        .find_iter(marker)
+        .map(|marker_pos| {
+            find_gacha_url_at(content, marker_pos, marker, url_start, end_marker)
+                .map(|(_, url)| url)
+        })
+        .collect()
+}
+
+// A gacha URL found by scan_for_urls().
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FoundUrl<'a> {
+    pub url: String,
+    // The game type whose markers found the URL.
+    pub game_type: &'a GameTypeData,
+}
+
+// Searches |content| for the URLs of every game type in |game_types|, e.g. in a HAR export or a
+// memory dump. Unlike find_gacha_urls_in_slice(), markers that are not in a URL are skipped. Each
+// URL is returned once, in the order that they appear in |content|.
+pub fn scan_for_urls<'a>(content: &[u8], game_types: &'a [GameTypeData]) -> Vec<FoundUrl<'a>> {
+    let mut found = vec![];
+    for game_type in game_types {
+        for marker_pos in content.find_iter(&game_type.marker) {
+            let result = find_gacha_url_at(
+                content,
+                marker_pos,
+                &game_type.marker,
+                &game_type.url_start,
+                &game_type.url_end,
+            );
+            match result {
+                Ok((url_pos, url)) => found.push((url_pos, FoundUrl { url, game_type })),
+                Err(e) => log::debug!(
+                    "Skipping {} marker at {}: {}",
+                    game_type.name,
+                    marker_pos,
+                    e
+                ),
+            }
+        }
+    }
+    // The sort is stable, so a URL found for more than one game type keeps the first one.
+    found.sort_by_key(|(url_pos, _)| *url_pos);
+    let mut seen_urls = HashSet::new();
+    found
+        .into_iter()
+        .map(|(_, found)| found)
+        .filter(|found| seen_urls.insert(found.url.clone()))
+        .collect()
+}
+
+// Returns the URL that contains the |marker| at |marker_pos| in |content|, along with the position
+// of the URL.
+fn find_gacha_url_at(
+    content: &[u8],
+    marker_pos: usize,
+    marker: &str,
+    url_start: &str,
+    end_marker: &str,
+) -> anyhow::Result<(usize, String)> {
+    let gacha_marker_end = marker_pos + marker.len();
+    let rest_of_content = &content[gacha_marker_end..];
+
+    // Note that this variable contains the index from the beginning of |content|.
+    let url_end_pos = rest_of_content
+        .find(end_marker)
+        .context("Failed to find end marker")?
+        + end_marker.len()
+        + gacha_marker_end;
+
+    // Since URLs can only be a certain length, the value in this variable is used to slice
+    // |content| to find the beginning of the URL.
+    let url_search_start_pos = url_end_pos.saturating_sub(MAX_URL_LENGTH);
+
+    let potential_url_slice = &content[url_search_start_pos..url_end_pos];
+
+    // Although there could be multiple URLs in the slice, since the slice ends
+    // with the end marker, the last occurrence of the url start marker must
+    // be the start of the URL.
+    let url_start_pos = potential_url_slice
+        .rfind(url_start)
+        .context("Failed to find url start")?;
+
+    let url = String::from_utf8(potential_url_slice[url_start_pos..].to_vec())
+        .context("Failed to convert URL to string")?;
+    Ok((url_search_start_pos + url_start_pos, url))
+}
+
+// The other tests are in tests/. These ones write caches with test_util, which is only built for
+// unit tests.
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Result;
+    use tempfile::tempdir;
+
+    // The built-in game types, but for http:// URLs, so that the URLs in the cache can be served
+    // by mockito.
+    fn http_registry() -> GameRegistry {
+        let mut registry = GameRegistry::builtin();
+        for game_type in &mut registry.games {
+            game_type.url_start = "http://".to_string();
+        }
+        registry
+    }
+
+    #[test]
+    fn test_zzz_pull_extractor_extract_from_cache_entries() -> Result<()> {
+        let dir = tempdir()?;
+        let cache_data_dir = dir
+            .path()
+            .join("ZenlessZoneZero_Data")
+            .join("webCaches")
+            .join("4.5.6.7")
+            .join("Cache")
+            .join("Cache_Data");
+        std::fs::create_dir_all(&cache_data_dir)?;
+        let extractor = PullExtractor::with_registry(dir.path(), http_registry())?;
+
+        let mut server = mockito::Server::new();
+        let url = format!(
+            "{}{}{}{}",
+            "http://",
+            &server.host_with_port(),
+            // Long enough to take several blocks of the entry.
+            "/getGachaLog/index.html?lang=en&authkey=key&authkey_ver=2&sign_type=sometype&game_biz=nap_global",
+            "&padding=".to_string() + &"x".repeat(200)
+        );
+        let key = format!("1/0/_dk_https://a.com https://a.com {}", url);
+        cache::blockfile::test_util::write_cache(
+            &cache_data_dir,
+            &[
+                cache::blockfile::test_util::TestEntry {
+                    key: "https://a.com/unrelated.js",
+                    creation_time: 1_000_000,
+                    last_used: 1_000_000,
+                },
+                cache::blockfile::test_util::TestEntry {
+                    key: &key,
+                    creation_time: 2_000_000,
+                    last_used: 2_000_000,
+                },
+            ],
+        )?;
+
+        let mock = server
+            .mock("GET", "/getGachaLog/index.html")
+            .match_query(mockito::Matcher::Any)
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body(r#"{"retcode": 0}"#)
+            .create();
+
+        let result = extractor.extract_url()?;
+        assert_eq!("http://127.0.0.1/getGachaLog/index.html?lang=en&authkey=key&authkey_ver=2&sign_type=sometype&game_biz=nap_global", result.url);
+        mock.assert();
+
+        Ok(())
+    }
+
+    // A URL whose entry was evicted from the index is still found in data_2.
+    #[test]
+    fn test_pull_extractor_scans_data2_when_index_has_no_urls() -> Result<()> {
+        let dir = tempdir()?;
+        let cache_data_dir = dir
+            .path()
+            .join("ZenlessZoneZero_Data")
+            .join("webCaches")
+            .join("4.5.6.7")
+            .join("Cache")
+            .join("Cache_Data");
+        std::fs::create_dir_all(&cache_data_dir)?;
+        cache::blockfile::test_util::write_cache(
+            &cache_data_dir,
+            &[cache::blockfile::test_util::TestEntry {
+                key: "https://a.com/unrelated.js",
+                creation_time: 1_000_000,
+                last_used: 1_000_000,
+            }],
+        )?;
+        let extractor = PullExtractor::with_registry(dir.path(), http_registry())?;
+
+        let mut server = mockito::Server::new();
+        let url = format!(
+            "{}{}{}",
+            "http://",
+            &server.host_with_port(),
+            "/getGachaLog/index.html?lang=en&authkey=evicted&authkey_ver=2&sign_type=sometype&game_biz=nap_global"
+        );
+        let mut data2 = std::fs::read(cache_data_dir.join("data_2"))?;
+        data2.extend(format!("\0\0{}\0\0", url).as_bytes());
+        std::fs::write(cache_data_dir.join("data_2"), data2)?;
+
+        let mock = server
+            .mock("GET", "/getGachaLog/index.html")
+            .match_query(mockito::Matcher::Any)
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body(r#"{"retcode": 0}"#)
+            .create();
+
+        let result = extractor.extract_url()?;
+        assert_eq!("http://127.0.0.1/getGachaLog/index.html?lang=en&authkey=evicted&authkey_ver=2&sign_type=sometype&game_biz=nap_global", result.url);
+        mock.assert();
+
+        Ok(())
+    }
+
+    // The newest URL should be tried first, even if it comes later in the cache.
+    #[test]
+    fn test_pull_extractor_extract_newest_first() -> Result<()> {
+        let dir = tempdir()?;
+        let cache_data_dir = dir
+            .path()
+            .join("ZenlessZoneZero_Data")
+            .join("webCaches")
+            .join("4.5.6.7")
+            .join("Cache")
+            .join("Cache_Data");
+        std::fs::create_dir_all(&cache_data_dir)?;
+        let extractor = PullExtractor::with_registry(dir.path(), http_registry())?
+            // One by one, so that the old URL is never requested.
+            .with_parallelism(1);
+
+        let mut server = mockito::Server::new();
+        let old_url = format!(
+            "http://{}/getGachaLog?authkey=old&game_biz=nap_global",
+            &server.host_with_port()
+        );
+        let new_url = format!(
+            "http://{}/getGachaLog?authkey=new&game_biz=nap_global",
+            &server.host_with_port()
+        );
+        cache::blockfile::test_util::write_cache(
+            &cache_data_dir,
+            &[
+                cache::blockfile::test_util::TestEntry {
+                    key: &old_url,
+                    creation_time: 1_000_000,
+                    last_used: 1_000_000,
+                },
+                cache::blockfile::test_util::TestEntry {
+                    key: &new_url,
+                    creation_time: 2_000_000,
+                    last_used: 3_000_000,
+                },
+            ],
+        )?;
+
+        let old_mock = server
+            .mock("GET", "/getGachaLog?authkey=old&game_biz=nap_global")
+            .with_status(200)
+            .with_body(r#"{"retcode": 0}"#)
+            .expect(0)
+            .create();
+        let new_mock = server
+            .mock("GET", "/getGachaLog?authkey=new&game_biz=nap_global")
+            .with_status(200)
+            .with_body(r#"{"retcode": 0}"#)
+            .create();
+
+        let result = extractor.extract_url()?;
+        assert_eq!(
+            "http://127.0.0.1/getGachaLog?authkey=new&game_biz=nap_global",
+            result.url
+        );
+        assert_eq!(
+            Some(SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(3)),
+            result.timestamp
+        );
+        old_mock.assert();
+        new_mock.assert();
+        Ok(())
+    }
+
+    // The URLs that were checked before are not requested again, unless they failed for a reason
+    // that can go away, e.g. an error of the server.
+    #[test]
+    fn test_pull_extractor_check_new_urls() -> Result<()> {
+        let dir = tempdir()?;
+        let cache_data_dir = dir
+            .path()
+            .join("ZenlessZoneZero_Data")
+            .join("webCaches")
+            .join("4.5.6.7")
+            .join("Cache")
+            .join("Cache_Data");
+        std::fs::create_dir_all(&cache_data_dir)?;
+        let extractor = PullExtractor::with_registry(dir.path(), http_registry())?;
+
+        let mut server = mockito::Server::new();
+        let url = |authkey: &str| {
+            format!(
+                "http://{}/getGachaLog?authkey={}&game_biz=nap_global",
+                &server.host_with_port(),
+                authkey
+            )
+        };
+        let urls = [url("working"), url("expired"), url("server_error")];
+        let entries = urls
+            .iter()
+            .map(|url| cache::blockfile::test_util::TestEntry {
+                key: url,
+                creation_time: 1_000_000,
+                last_used: 1_000_000,
+            })
+            .collect::<Vec<_>>();
+        cache::blockfile::test_util::write_cache(&cache_data_dir, &entries)?;
+        let mut mock = |authkey: &str, body: &str, hits: usize| {
+            server
+                .mock(
+                    "GET",
+                    format!("/getGachaLog?authkey={}&game_biz=nap_global", authkey).as_str(),
+                )
+                .with_status(200)
+                .with_body(body)
+                .expect(hits)
+                .create()
+        };
+        let working = mock("working", r#"{"retcode": 0}"#, 1);
+        let expired = mock(
+            "expired",
+            r#"{"retcode": -101, "message": "authkey timeout"}"#,
+            1,
+        );
+        let server_error = mock(
+            "server_error",
+            r#"{"retcode": -1, "message": "system error"}"#,
+            2,
+        );
+
+        let mut checked_authkeys = HashSet::new();
+        assert_eq!(3, extractor.check_new_urls(&mut checked_authkeys)?.len());
+        let checked = extractor.check_new_urls(&mut checked_authkeys)?;
+        assert_eq!(1, checked.len());
+        assert!(matches!(
+            checked[0].error,
+            Some(Error::Api { retcode: -1, .. })
+        ));
+        working.assert();
+        expired.assert();
+        server_error.assert();
+        Ok(())
+    }
+
+    #[test]
+    fn test_pull_extractor_extract_from_simple_cache() -> Result<()> {
+        let dir = tempdir()?;
+        let cache_data_dir = dir
+            .path()
+            .join("StarRail_Data")
+            .join("webCaches")
+            .join("2.5.0.0")
+            .join("Cache")
+            .join("Cache_Data");
+        std::fs::create_dir_all(&cache_data_dir)?;
+
+        let mut server = mockito::Server::new();
+        let url = format!(
+            "http://{}/getGachaLog?authkey=key&game_biz=hkrpg_global",
+            &server.host_with_port()
+        );
+        cache::simple::test_util::write_cache(
+            &cache_data_dir,
+            &["https://a.com/unrelated.js", &url],
+        )?;
+
+        let mock = server
+            .mock("GET", "/getGachaLog?authkey=key&game_biz=hkrpg_global")
+            .with_status(200)
+            .with_body(r#"{"retcode": 0}"#)
+            .create();
+
+        let extractor = PullExtractor::with_registry(dir.path(), http_registry())?;
+        let result = extractor.extract_url()?;
+        assert_eq!(
+            "http://127.0.0.1/getGachaLog?authkey=key&game_biz=hkrpg_global",
+            result.url
+        );
+        mock.assert();
+        Ok(())
+    }
+}
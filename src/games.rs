@@ -0,0 +1,377 @@
+use anyhow::{Context, Result};
+use itertools::Itertools;
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+// The game definitions that are built into the binary. A user supplied definitions file uses the
+// same format, and can override or extend these.
+const BUILTIN_GAME_DEFINITIONS: &str = include_str!("games.json");
+
+// Global and CN (mainland China) clients talk to different API hosts and use different game_biz
+// values, so they are handled as separate game types.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Region {
+    Global,
+    China,
+}
+
+impl fmt::Display for Region {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Region::Global => write!(f, "global"),
+            Region::China => write!(f, "china"),
+        }
+    }
+}
+
+// How to check whether a URL found in the cache still works.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Validation {
+    // The cached URL is the gacha history web page (e.g. Genshin). The API endpoint is built from
+    // its query, with the host and path replaced and the query overrides applied.
+    WishPage {
+        api_host: String,
+        api_path: String,
+        #[serde(default)]
+        query_overrides: BTreeMap<String, String>,
+    },
+    // The cached URL is the getGachaLog API URL itself (e.g. ZZZ and Star Rail), so it can be
+    // requested as is.
+    GachaLogApi,
+}
+
+impl Validation {
+    // Returns the getGachaLog API URL for |url| found in the cache.
+    pub fn api_url(&self, url: &str) -> Result<Url> {
+        match self {
+            Validation::WishPage {
+                api_host,
+                api_path,
+                query_overrides,
+            } => wish_page_api_url(url, api_host, api_path, query_overrides),
+            Validation::GachaLogApi => {
+                Url::parse(url).with_context(|| format!("{} is not a valid URL", url))
+            }
+        }
+    }
+}
+
+// A banner that has its own gacha history.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct Banner {
+    // The value of the gacha type query parameter for this banner.
+    pub gacha_type: String,
+    pub name: String,
+    // Whether the highest rank items of this banner are a 50/50 between the featured items and
+    // the standard items, e.g. character event banners.
+    #[serde(default)]
+    pub fifty_fifty: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct GameTypeData {
+    pub name: String,
+    pub data_dir_name: String,
+    pub region: Region,
+    // Unity logs of the game that contain the install path, relative to AppData/LocalLow in the
+    // user profile.
+    #[serde(default)]
+    pub player_logs: Vec<String>,
+    pub marker: String,
+    pub url_start: String,
+    pub url_end: String,
+    pub validation: Validation,
+    // The query parameter that selects the banner when fetching the gacha history.
+    #[serde(default = "default_gacha_type_param")]
+    pub gacha_type_param: String,
+    #[serde(default)]
+    pub banners: Vec<Banner>,
+    // The rank_type of the highest rank items, e.g. 5 for 5 star items. ZZZ's S rank is 4.
+    #[serde(default = "default_highest_rank_type")]
+    pub highest_rank_type: u32,
+    // The highest rank items that are not featured, i.e. the ones that lose a 50/50.
+    #[serde(default)]
+    pub standard_items: Vec<StandardItem>,
+}
+
+// A highest rank item of the standard pool.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct StandardItem {
+    // The English name.
+    pub name: String,
+    // The day that the item joined the standard pool, e.g. "2022-09-28", for the items that were
+    // featured before. None if it always was in the pool.
+    #[serde(default)]
+    pub since: Option<String>,
+}
+
+impl GameTypeData {
+    // Whether pulling |name| at |time| lost the 50/50. |time| is the time of a gacha record, e.g.
+    // "2022-09-28 12:00:00", which starts with the day in the same format as
+    // StandardItem::since, so that the two compare as strings.
+    pub fn is_standard_item(&self, name: &str, time: &str) -> bool {
+        self.standard_items.iter().any(|item| {
+            item.name == name
+                && item
+                    .since
+                    .as_ref()
+                    .is_none_or(|since| time >= since.as_str())
+        })
+    }
+
+    // The game_biz of the URLs of this game type, e.g. "hk4e_global".
+    pub fn game_biz(&self) -> Option<&str> {
+        self.url_end.strip_prefix("game_biz=")
+    }
+}
+
+fn default_gacha_type_param() -> String {
+    "gacha_type".to_string()
+}
+
+fn default_highest_rank_type() -> u32 {
+    5
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct GameRegistry {
+    pub games: Vec<GameTypeData>,
+}
+
+impl GameRegistry {
+    pub fn builtin() -> Self {
+        // The built-in definitions are checked by the tests, so this never fails at runtime.
+        Self::from_json(BUILTIN_GAME_DEFINITIONS).expect("Built-in game definitions are invalid")
+    }
+
+    pub fn from_json(json: &str) -> Result<Self> {
+        serde_json::from_str(json).context("Failed to parse game definitions")
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        let json = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read game definitions {}", path.display()))?;
+        Self::from_json(&json)
+    }
+
+    // Merges |other| into this registry. An entry with the same data directory and region as an
+    // existing entry replaces it. Other entries are appended.
+    pub fn merge(&mut self, other: GameRegistry) {
+        for game in other.games {
+            let existing = self.games.iter_mut().find(|existing| {
+                existing.data_dir_name == game.data_dir_name && existing.region == game.region
+            });
+            match existing {
+                Some(existing) => *existing = game,
+                None => self.games.push(game),
+            }
+        }
+    }
+
+    // Returns the game type of |url|, told by its game_biz parameter.
+    pub fn find_by_url(&self, url: &str) -> Option<&GameTypeData> {
+        let url = Url::parse(url).ok()?;
+        let (_, game_biz) = url.query_pairs().find(|(key, _)| key == "game_biz")?;
+        self.games
+            .iter()
+            .find(|game| game.game_biz() == Some(game_biz.as_ref()))
+    }
+
+    // Returns the game types that |query| names. |query| is a part of a game name (e.g. "star
+    // rail") or of a data directory name (e.g. "YuanShen"), ignoring case, spaces and
+    // punctuation, or a game_biz (e.g. "hkrpg_cn").
+    pub fn select(&self, query: &str) -> GameRegistry {
+        let simplify = |s: &str| {
+            s.chars()
+                .filter(|c| c.is_alphanumeric())
+                .flat_map(char::to_lowercase)
+                .collect::<String>()
+        };
+        let simple_query = simplify(query);
+        let games = self
+            .games
+            .iter()
+            .filter(|game| {
+                game.game_biz() == Some(query)
+                    || (!simple_query.is_empty()
+                        && (simplify(&game.name).contains(&simple_query)
+                            || simplify(&game.data_dir_name).contains(&simple_query)))
+            })
+            .cloned()
+            .collect();
+        GameRegistry { games }
+    }
+}
+
+// Builds the getGachaLog API URL from a gacha history web page |url|, by replacing the host and
+// the path and applying |query_overrides|.
+pub fn wish_page_api_url(
+    url: &str,
+    api_host: &str,
+    api_path: &str,
+    query_overrides: &BTreeMap<String, String>,
+) -> Result<Url> {
+    let mut uri = Url::parse(url).with_context(|| format!("{} is not a valid URL", url))?;
+
+    uri.set_path(api_path);
+    uri.set_host(Some(api_host))
+        .with_context(|| format!("Failed to set host to {}", api_host))?;
+    uri.set_fragment(None);
+
+    override_query_params(&mut uri, query_overrides)?;
+    Ok(uri)
+}
+
+// Sets the query parameters in |overrides| on |uri|, replacing the existing values.
+pub fn override_query_params<K, V>(
+    uri: &mut Url,
+    overrides: impl IntoIterator<Item = (K, V)>,
+) -> Result<()>
+where
+    K: AsRef<str>,
+    V: AsRef<str>,
+{
+    let mut query_params: HashMap<String, String> = uri
+        .query_pairs()
+        .map(|(key, value)| (Cow::into_owned(key), Cow::into_owned(value)))
+        .collect();
+    for (key, value) in overrides {
+        query_params.insert(key.as_ref().to_string(), value.as_ref().to_string());
+    }
+
+    // Sort the parameters, to make testing easier.
+    let query_params = query_params
+        .keys()
+        .sorted()
+        .map(|key| (key, query_params.get(key).unwrap()))
+        .collect_vec();
+
+    uri.set_query(Some(
+        &serde_urlencoded::to_string(&query_params).context("Failed to set query params")?,
+    ));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builtin_definitions_parse() {
+        let registry = GameRegistry::builtin();
+        assert!(registry
+            .games
+            .iter()
+            .any(|game| game.data_dir_name == "GenshinImpact_Data"));
+        assert!(registry
+            .games
+            .iter()
+            .any(|game| game.data_dir_name == "ZenlessZoneZero_Data"));
+        assert!(registry
+            .games
+            .iter()
+            .any(|game| game.data_dir_name == "StarRail_Data"));
+    }
+
+    #[test]
+    fn merge_overrides_and_extends() -> Result<()> {
+        let mut registry = GameRegistry::builtin();
+        let builtin_len = registry.games.len();
+
+        let user = GameRegistry::from_json(
+            r#"{
+                "games": [
+                    {
+                        "name": "Genshin Impact",
+                        "data_dir_name": "GenshinImpact_Data",
+                        "region": "global",
+                        "marker": "new_marker",
+                        "url_start": "https://",
+                        "url_end": "game_biz=hk4e_global",
+                        "validation": {
+                            "kind": "wish_page",
+                            "api_host": "new-host.example.com",
+                            "api_path": "gacha_info/api/getGachaLog"
+                        }
+                    },
+                    {
+                        "name": "New Game",
+                        "data_dir_name": "NewGame_Data",
+                        "region": "global",
+                        "marker": "getGachaLog",
+                        "url_start": "https://",
+                        "url_end": "game_biz=new_global",
+                        "validation": { "kind": "gacha_log_api" }
+                    }
+                ]
+            }"#,
+        )?;
+        registry.merge(user);
+
+        assert_eq!(builtin_len + 1, registry.games.len());
+        let genshin = registry
+            .games
+            .iter()
+            .find(|game| game.data_dir_name == "GenshinImpact_Data")
+            .unwrap();
+        assert_eq!("new_marker", genshin.marker);
+        assert_eq!(
+            Validation::WishPage {
+                api_host: "new-host.example.com".to_string(),
+                api_path: "gacha_info/api/getGachaLog".to_string(),
+                query_overrides: BTreeMap::new(),
+            },
+            genshin.validation
+        );
+        assert_eq!("NewGame_Data", registry.games.last().unwrap().data_dir_name);
+        Ok(())
+    }
+
+    #[test]
+    fn select_games() {
+        let registry = GameRegistry::builtin();
+        let biz = |registry: GameRegistry| {
+            registry
+                .games
+                .iter()
+                .map(|game| game.game_biz().unwrap().to_string())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            vec!["hkrpg_global", "hkrpg_cn"],
+            biz(registry.select("Star Rail"))
+        );
+        assert_eq!(vec!["hk4e_cn"], biz(registry.select("yuanshen")));
+        assert_eq!(vec!["nap_cn"], biz(registry.select("nap_cn")));
+        assert!(registry.select("nap").games.is_empty());
+        assert!(registry.select(" ").games.is_empty());
+    }
+
+    #[test]
+    fn find_by_url() {
+        let registry = GameRegistry::builtin();
+        let game = registry
+            .find_by_url("https://a.com/getGachaLog?authkey=a&game_biz=hkrpg_cn&lang=en")
+            .unwrap();
+        assert_eq!("StarRail_Data", game.data_dir_name);
+        assert_eq!(Region::China, game.region);
+
+        assert!(registry
+            .find_by_url("https://a.com/getGachaLog?authkey=a&game_biz=unknown")
+            .is_none());
+        assert!(registry.find_by_url("https://a.com/getGachaLog").is_none());
+        assert!(registry.find_by_url("game_biz=hk4e_global").is_none());
+    }
+
+    #[test]
+    fn invalid_definitions() {
+        assert!(GameRegistry::from_json(r#"{"games": [{"name": "Missing fields"}]}"#).is_err());
+    }
+}
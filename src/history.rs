@@ -0,0 +1,484 @@
+// Fetches the full gacha history with a working gacha URL.
+
+use crate::games::{Banner, GameTypeData};
+use crate::validate::{self, HttpClient};
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::thread;
+use std::time::Duration;
+
+// The maximum page size that the API accepts.
+const PAGE_SIZE: u32 = 20;
+// The API returns an error when it is requested too frequently, so this is waited before every
+// request after the first one.
+const REQUEST_INTERVAL: Duration = Duration::from_millis(300);
+// The language of the item names in the records. The statistics compare the names with the
+// English names of the standard items, so the records are always fetched in English, whatever
+// the language of the URL.
+const RECORD_LANG: &str = "en-us";
+
+// A single pull, as returned by the getGachaLog API. The API returns all the values as strings.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GachaRecord {
+    pub id: String,
+    pub uid: String,
+    pub gacha_type: String,
+    // Star Rail and ZZZ only.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub gacha_id: Option<String>,
+    #[serde(default)]
+    pub item_id: String,
+    #[serde(default)]
+    pub count: String,
+    pub time: String,
+    pub name: String,
+    #[serde(default)]
+    pub lang: String,
+    pub item_type: String,
+    pub rank_type: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BannerHistory {
+    // The gacha type that was used to query this banner. Note that records may have a different
+    // gacha type, e.g. Genshin's second character event banner (400) is returned for 301.
+    pub gacha_type: String,
+    pub name: String,
+    // Newest first, as returned by the API.
+    pub records: Vec<GachaRecord>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GachaHistory {
+    pub game: String,
+    pub game_biz: String,
+    // Empty if there are no records.
+    pub uid: String,
+    // Hours from UTC of the times in the records, if the API returned it.
+    pub region_time_zone: Option<i32>,
+    pub lang: String,
+    pub banners: Vec<BannerHistory>,
+}
+
+impl GachaHistory {
+    pub fn record_count(&self) -> usize {
+        self.banners.iter().map(|banner| banner.records.len()).sum()
+    }
+}
+
+// A page of records, i.e. the "data" field of the response.
+#[derive(Debug, Deserialize)]
+struct GachaLogPage {
+    list: Vec<GachaRecord>,
+    #[serde(default)]
+    region_time_zone: Option<i32>,
+}
+
+// Compares record ids, which are numbers that increase with time. They are too big for some JSON
+// parsers, so the API returns them as strings.
+pub fn compare_ids(a: &str, b: &str) -> Ordering {
+    (a.len(), a).cmp(&(b.len(), b))
+}
+
+// Fetches the records of all the banners of |game_type| with |url|, which must be a URL that
+// passed the validation. The requests are sent with |client|, like the validation ones.
+pub fn fetch_history(
+    client: &dyn HttpClient,
+    game_type: &GameTypeData,
+    url: &str,
+) -> Result<GachaHistory> {
+    fetch_new_history(client, game_type, url, |_| false)
+}
+
+// Same as fetch_history(), but stops fetching a banner at the first record that |is_known|
+// returns true for. Since the records are returned newest first, only the records newer than the
+// known ones are returned. Note that the uid is set even if there are no new records.
+pub fn fetch_new_history(
+    client: &dyn HttpClient,
+    game_type: &GameTypeData,
+    url: &str,
+    is_known: impl Fn(&GachaRecord) -> bool,
+) -> Result<GachaHistory> {
+    let mut api_url = game_type.validation.api_url(url)?;
+    crate::games::override_query_params(&mut api_url, [("lang", RECORD_LANG)])?;
+
+    let mut history = GachaHistory {
+        game: game_type.name.clone(),
+        game_biz: String::new(),
+        uid: String::new(),
+        region_time_zone: None,
+        lang: String::new(),
+        banners: vec![],
+    };
+    history.game_biz = query_value(&api_url, "game_biz").unwrap_or_default();
+    history.lang = query_value(&api_url, "lang").unwrap_or_default();
+
+    for (i, banner) in game_type.banners.iter().enumerate() {
+        // Like between the pages of a banner, so that the API does not rate limit the requests.
+        if i > 0 {
+            thread::sleep(REQUEST_INTERVAL);
+        }
+        fetch_banner(
+            client,
+            &api_url,
+            &game_type.gacha_type_param,
+            banner,
+            &is_known,
+            &mut history,
+        )?;
+    }
+    Ok(history)
+}
+
+// Returns the game_biz parameter of |url|, e.g. hk4e_global.
+pub fn game_biz(url: &str) -> Option<String> {
+    query_value(&reqwest::Url::parse(url).ok()?, "game_biz")
+}
+
+fn query_value(url: &reqwest::Url, key: &str) -> Option<String> {
+    url.query_pairs()
+        .find(|(k, _)| k == key)
+        .map(|(_, value)| value.into_owned())
+}
+
+// Fetches the records of |banner|, following the pages with end_id, and adds them to |history|.
+fn fetch_banner(
+    client: &dyn HttpClient,
+    api_url: &reqwest::Url,
+    gacha_type_param: &str,
+    banner: &Banner,
+    is_known: &dyn Fn(&GachaRecord) -> bool,
+    history: &mut GachaHistory,
+) -> Result<()> {
+    let mut records: Vec<GachaRecord> = vec![];
+    let mut page = 1;
+    loop {
+        let end_id = records
+            .last()
+            .map(|record| record.id.clone())
+            .unwrap_or_else(|| "0".to_string());
+        let mut page_url = api_url.clone();
+        crate::games::override_query_params(
+            &mut page_url,
+            [
+                (gacha_type_param, banner.gacha_type.as_str()),
+                ("size", &PAGE_SIZE.to_string()),
+                ("page", &page.to_string()),
+                ("end_id", &end_id),
+            ],
+        )?;
+
+        let page_data = fetch_page(client, &page_url)
+            .with_context(|| format!("Failed to fetch page {} of {}", page, banner.name))?;
+        history.region_time_zone = history.region_time_zone.or(page_data.region_time_zone);
+        let Some(first) = page_data.list.first() else {
+            break;
+        };
+        if history.uid.is_empty() {
+            history.uid = first.uid.clone();
+        }
+
+        match page_data.list.iter().position(is_known) {
+            Some(known) => {
+                records.extend(page_data.list.into_iter().take(known));
+                break;
+            }
+            None => records.extend(page_data.list),
+        }
+        page += 1;
+        thread::sleep(REQUEST_INTERVAL);
+    }
+
+    log::debug!("Fetched {} records for {}", records.len(), banner.name);
+    history.banners.push(BannerHistory {
+        gacha_type: banner.gacha_type.clone(),
+        name: banner.name.clone(),
+        records,
+    });
+    Ok(())
+}
+
+fn fetch_page(client: &dyn HttpClient, url: &reqwest::Url) -> Result<GachaLogPage> {
+    let response = validate::check_response(url, &client.get(url)?)?;
+    let data = response
+        .get("data")
+        .context("Response JSON did not contain a data field")?;
+    serde_json::from_value(data.clone()).context("Failed to parse gacha log page")
+}
+
+// Builds gacha histories for tests.
+#[cfg(test)]
+pub mod test_util {
+    use super::*;
+
+    pub const UID: &str = "800000000";
+
+    // A 3 star weapon named "Item <id>". Tests that need other values override them with the
+    // struct update syntax.
+    pub fn record(id: &str, gacha_type: &str) -> GachaRecord {
+        GachaRecord {
+            id: id.to_string(),
+            uid: UID.to_string(),
+            gacha_type: gacha_type.to_string(),
+            gacha_id: None,
+            item_id: "".to_string(),
+            count: "1".to_string(),
+            time: "2024-01-01 00:00:00".to_string(),
+            name: format!("Item {}", id),
+            lang: "en-us".to_string(),
+            item_type: "Weapon".to_string(),
+            rank_type: "3".to_string(),
+        }
+    }
+
+    // The record as the API returns it.
+    pub fn record_json(id: &str, gacha_type: &str) -> String {
+        serde_json::to_string(&record(id, gacha_type)).unwrap()
+    }
+
+    // |records| are newest first, like the API returns them.
+    pub fn banner(gacha_type: &str, records: Vec<GachaRecord>) -> BannerHistory {
+        BannerHistory {
+            gacha_type: gacha_type.to_string(),
+            name: format!("Banner {}", gacha_type),
+            records,
+        }
+    }
+
+    pub fn history(game_biz: &str, banners: Vec<BannerHistory>) -> GachaHistory {
+        GachaHistory {
+            game: "Test Game".to_string(),
+            game_biz: game_biz.to_string(),
+            uid: UID.to_string(),
+            region_time_zone: None,
+            lang: "en-us".to_string(),
+            banners,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::test_util::*;
+    use super::*;
+    use crate::games::{Region, StandardItem, Validation};
+    use reqwest::blocking::Client;
+
+    fn test_game_type() -> GameTypeData {
+        GameTypeData {
+            name: "Test Game".to_string(),
+            data_dir_name: "Test_Data".to_string(),
+            region: Region::Global,
+            player_logs: vec![],
+            marker: "getGachaLog".to_string(),
+            url_start: "https://".to_string(),
+            url_end: "game_biz=test_global".to_string(),
+            validation: Validation::GachaLogApi,
+            gacha_type_param: "gacha_type".to_string(),
+            banners: vec![
+                Banner {
+                    gacha_type: "1".to_string(),
+                    name: "Standard".to_string(),
+                    fifty_fifty: false,
+                },
+                Banner {
+                    gacha_type: "2".to_string(),
+                    name: "Event".to_string(),
+                    fifty_fifty: true,
+                },
+            ],
+            highest_rank_type: 5,
+            standard_items: vec![],
+        }
+    }
+
+    fn page_json(records: &[String]) -> String {
+        format!(
+            r#"{{"retcode": 0, "message": "OK",
+                "data": {{"page": "1", "size": "20", "list": [{}], "region_time_zone": 8}}}}"#,
+            records.join(",")
+        )
+    }
+
+    #[test]
+    fn fetch_all_pages_of_all_banners() -> Result<()> {
+        let mut server = mockito::Server::new();
+        let url = format!(
+            "http://{}/getGachaLog?authkey=key&game_biz=test_global&lang=en",
+            server.host_with_port()
+        );
+
+        let first_page = server
+            .mock("GET", "/getGachaLog?authkey=key&end_id=0&gacha_type=1&game_biz=test_global&lang=en-us&page=1&size=20")
+            .with_status(200)
+            .with_body(page_json(&[record_json("3", "1"), record_json("2", "1")]))
+            .create();
+        let second_page = server
+            .mock("GET", "/getGachaLog?authkey=key&end_id=2&gacha_type=1&game_biz=test_global&lang=en-us&page=2&size=20")
+            .with_status(200)
+            .with_body(page_json(&[record_json("1", "1")]))
+            .create();
+        let last_page = server
+            .mock("GET", "/getGachaLog?authkey=key&end_id=1&gacha_type=1&game_biz=test_global&lang=en-us&page=3&size=20")
+            .with_status(200)
+            .with_body(page_json(&[]))
+            .create();
+        let empty_banner = server
+            .mock("GET", "/getGachaLog?authkey=key&end_id=0&gacha_type=2&game_biz=test_global&lang=en-us&page=1&size=20")
+            .with_status(200)
+            .with_body(page_json(&[]))
+            .create();
+
+        let history = fetch_history(&Client::new(), &test_game_type(), &url)?;
+        assert_eq!("test_global", history.game_biz);
+        assert_eq!(UID, history.uid);
+        assert_eq!(Some(8), history.region_time_zone);
+        assert_eq!(2, history.banners.len());
+        assert_eq!(
+            vec!["3", "2", "1"],
+            history.banners[0]
+                .records
+                .iter()
+                .map(|record| record.id.as_str())
+                .collect::<Vec<_>>()
+        );
+        assert!(history.banners[1].records.is_empty());
+        assert_eq!(3, history.record_count());
+
+        first_page.assert();
+        second_page.assert();
+        last_page.assert();
+        empty_banner.assert();
+        Ok(())
+    }
+
+    #[test]
+    fn fetch_fails_on_error_retcode() {
+        let mut server = mockito::Server::new();
+        let url = format!(
+            "http://{}/getGachaLog?authkey=key&game_biz=test_global",
+            server.host_with_port()
+        );
+        let _mock = server
+            .mock("GET", mockito::Matcher::Any)
+            .with_status(200)
+            .with_body(r#"{"retcode": -101, "message": "authkey timeout", "data": null}"#)
+            .create();
+
+        let error = fetch_history(&Client::new(), &test_game_type(), &url).unwrap_err();
+        // The error is classified like in the validation, so that the remedy can be shown.
+        assert!(matches!(
+            error.downcast_ref::<crate::Error>(),
+            Some(crate::Error::AuthkeyExpired { .. })
+        ));
+    }
+
+    #[test]
+    fn fetch_stops_at_known_record() -> Result<()> {
+        let mut server = mockito::Server::new();
+        let url = format!(
+            "http://{}/getGachaLog?authkey=key&game_biz=test_global&lang=en",
+            server.host_with_port()
+        );
+
+        let first_page = server
+            .mock("GET", "/getGachaLog?authkey=key&end_id=0&gacha_type=1&game_biz=test_global&lang=en-us&page=1&size=20")
+            .with_status(200)
+            .with_body(page_json(&[record_json("3", "1"), record_json("2", "1")]))
+            .create();
+        // Record 2 is already known, so the next page is not requested.
+        let second_page = server
+            .mock("GET", "/getGachaLog?authkey=key&end_id=3&gacha_type=1&game_biz=test_global&lang=en-us&page=2&size=20")
+            .expect(0)
+            .create();
+        let known_banner = server
+            .mock("GET", "/getGachaLog?authkey=key&end_id=0&gacha_type=2&game_biz=test_global&lang=en-us&page=1&size=20")
+            .with_status(200)
+            .with_body(page_json(&[record_json("1", "2")]))
+            .create();
+
+        let history = fetch_new_history(&Client::new(), &test_game_type(), &url, |record| {
+            compare_ids(&record.id, "2") != Ordering::Greater
+        })?;
+        assert_eq!(UID, history.uid);
+        assert_eq!(1, history.banners[0].records.len());
+        assert_eq!("3", history.banners[0].records[0].id);
+        assert!(history.banners[1].records.is_empty());
+
+        first_page.assert();
+        second_page.assert();
+        known_banner.assert();
+        Ok(())
+    }
+
+    // The statistics need the English names of the items, so URLs in other languages are
+    // fetched in English.
+    #[test]
+    fn fetch_in_english() -> Result<()> {
+        let mut server = mockito::Server::new();
+        let url = format!(
+            "http://{}/getGachaLog?authkey=key&game_biz=test_global&lang=zh-cn",
+            server.host_with_port()
+        );
+        let standard_item = GachaRecord {
+            name: "Standard Item".to_string(),
+            rank_type: "5".to_string(),
+            ..record("1", "2")
+        };
+        let _standard_banner = server
+            .mock("GET", "/getGachaLog")
+            .match_query(mockito::Matcher::UrlEncoded(
+                "gacha_type".into(),
+                "1".into(),
+            ))
+            .with_status(200)
+            .with_body(page_json(&[]))
+            .create();
+        let event_banner = server
+            .mock("GET", "/getGachaLog")
+            .match_query(mockito::Matcher::AllOf(vec![
+                mockito::Matcher::UrlEncoded("gacha_type".into(), "2".into()),
+                mockito::Matcher::UrlEncoded("end_id".into(), "0".into()),
+                mockito::Matcher::UrlEncoded("lang".into(), "en-us".into()),
+            ]))
+            .with_status(200)
+            .with_body(page_json(&[serde_json::to_string(&standard_item)?]))
+            .expect(1)
+            .create();
+        // The next page of the event banner.
+        let _last_page = server
+            .mock("GET", "/getGachaLog")
+            .match_query(mockito::Matcher::UrlEncoded("end_id".into(), "1".into()))
+            .with_status(200)
+            .with_body(page_json(&[]))
+            .create();
+
+        let game_type = GameTypeData {
+            standard_items: vec![StandardItem {
+                name: "Standard Item".to_string(),
+                since: None,
+            }],
+            ..test_game_type()
+        };
+        let history = fetch_history(&Client::new(), &game_type, &url)?;
+        assert_eq!("en-us", history.lang);
+        let stats = crate::stats::HistoryStats::new(&history, &game_type);
+        assert_eq!(
+            Some(crate::stats::FiftyFiftyResult::Lost),
+            stats.banners[1].highest_rank_pulls[0].fifty_fifty
+        );
+        event_banner.assert();
+        Ok(())
+    }
+
+    #[test]
+    fn compare_record_ids() {
+        assert_eq!(Ordering::Less, compare_ids("9", "10"));
+        assert_eq!(
+            Ordering::Greater,
+            compare_ids("1700000000000000002", "1700000000000000001")
+        );
+        assert_eq!(Ordering::Equal, compare_ids("5", "5"));
+    }
+}
@@ -0,0 +1,20 @@
+// Extracts the gacha history URL of HoYoverse games from the web caches of the game, and fetches
+// and exports the gacha history with it. The extract-wish-url binary is a thin CLI on top of this.
+
+pub mod cache;
+pub mod discover;
+pub mod error;
+pub mod export;
+pub mod extractor;
+pub mod games;
+pub mod history;
+pub mod report;
+pub mod stats;
+pub mod store;
+pub mod uigf;
+pub mod validate;
+pub mod watch;
+
+pub use error::Error;
+pub use extractor::{CheckedUrl, ExtractedUrl, PullExtractor};
+pub use games::{GameRegistry, GameTypeData};
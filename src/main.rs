@@ -1,693 +1,1200 @@
-use anyhow::anyhow;
-use anyhow::bail;
 use anyhow::{Context, Result};
-use bstr::ByteSlice;
+use chrono::{DateTime, Local, Utc};
+use clap::{Args, Parser, Subcommand};
+use extract_wish_url::error::Remedy;
+use extract_wish_url::report::{CandidateStatus, ExtractionReport, ScanReport, ValidationReport};
+use extract_wish_url::validate::{self, HttpClient, UrlValidator};
+use extract_wish_url::watch::{self, CacheWatcher};
+use extract_wish_url::{cache, discover, export, extractor, history, stats, store, uigf};
+use extract_wish_url::{CheckedUrl, Error, ExtractedUrl, GameRegistry, PullExtractor};
 use itertools::Itertools;
-use reqwest::blocking::Client;
-use reqwest::Url;
-use serde_json::Value;
-use std::borrow::Cow;
-use std::cmp::Ordering;
-use std::collections::HashMap;
-use std::env;
+use std::collections::HashSet;
 use std::fs;
+use std::io::{self, Read};
 use std::path::Path;
 use std::path::PathBuf;
+use std::process::ExitCode;
+use std::time::SystemTime;
+
+// Exit codes, so that scripts can tell the failures apart. clap exits with EXIT_USAGE on invalid
+// arguments.
+const EXIT_FAILURE: u8 = 1;
+const EXIT_USAGE: u8 = 2;
+const EXIT_NO_INSTALL: u8 = 3;
+const EXIT_NO_URL: u8 = 4;
+const EXIT_URL_NOT_WORKING: u8 = 5;
+const EXIT_RETRY_LATER: u8 = 6;
+
+const EXIT_CODES_HELP: &str = "Exit codes:
+  0  Success
+  1  Other errors, e.g. failing to write a file
+  2  Invalid arguments
+  3  The game install directory was not found
+  4  The cache has no gacha URLs
+  5  None of the gacha URLs works, e.g. they have expired
+  6  Network error or too many requests, retrying later may work";
+
+#[derive(Parser)]
+#[command(
+    version,
+    about = "Finds the gacha history URL of HoYoverse games in the web cache of the game.",
+    after_help = EXIT_CODES_HELP,
+    args_conflicts_with_subcommands = true
+)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+    // Without a subcommand, the arguments of extract are accepted.
+    #[command(flatten)]
+    extract: ExtractArgs,
+    #[command(flatten)]
+    global: GlobalArgs,
+}
 
-const MAX_URL_LENGTH: usize = 2048;
-
-// Function type for checking the gacha URL (&str) passed in. Since the testing could transform
-// the URL, it returns a String on success.
-type TestGachaUrlFn = Box<dyn Fn(&str) -> Result<String>>;
-
-struct GameTypeData {
-    data_dir_name: &'static str,
-    marker: &'static str,
-    url_start: &'static str,
-    url_end: &'static str,
-    valid_url_check_fn: TestGachaUrlFn,
-}
-
-// Genshin's version folders have 4 numbers.
-// The field names are arbitrary names that I gave, not from any source.
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
-struct Version {
-    major: u32,
-    minor: u32,
-    patch: u32,
-    other: u32,
-}
-
-impl Ord for Version {
-    fn cmp(&self, other: &Self) -> Ordering {
-        self.major
-            .cmp(&other.major)
-            .then_with(|| self.minor.cmp(&other.minor))
-            .then_with(|| self.patch.cmp(&other.patch))
-            .then_with(|| self.other.cmp(&other.other))
-    }
-}
-
-impl PartialOrd for Version {
-    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-        Some(self.cmp(other))
-    }
-}
-
-struct VersionedDirectory {
-    path: PathBuf,
-    version: Version,
-}
-
-pub struct PullExtractor {
-    install_path: PathBuf,
-    game_type: GameTypeData,
-}
-
-impl PullExtractor {
-    pub fn new(install_path: &Path) -> Result<Self> {
-        let game_types = [
-            // Add more games here.
-            GameTypeData {
-                data_dir_name: "GenshinImpact_Data",
-                marker: "webview_gacha",
-                url_start: "https://",
-                url_end: "game_biz=hk4e_global",
-                valid_url_check_fn: Box::new(|url| {
-                    test_genshin_wish_url(url, "public-operation-hk4e-sg.hoyoverse.com")
-                }),
-            },
-            GameTypeData {
-                data_dir_name: "ZenlessZoneZero_Data",
-                marker: "getGachaLog",
-                url_start: "https://",
-                url_end: "game_biz=nap_global",
-                valid_url_check_fn: Box::new(|url: &str| test_zzz_signal_url(url)),
-            },
-        ];
-        let game_type = game_types
-            .into_iter()
-            .find_map(|game_type| {
-                let data_dir = install_path.join(game_type.data_dir_name);
-                if !data_dir.is_dir() {
-                    return None;
-                }
-                Some(game_type)
-            })
-            .with_context(|| {
-                format!(
-                    "Failed to find data directory in {}",
-                    install_path.display()
-                )
-            })?;
+#[derive(Args)]
+struct GlobalArgs {
+    #[arg(
+        long,
+        global = true,
+        value_name = "GAME",
+        help = "Only look for this game, e.g. \"genshin\", \"star rail\", \"YuanShen\" or \"nap_global\""
+    )]
+    game: Option<String>,
+    // A definitions file can override or extend the built-in game definitions, e.g. when a game
+    // changes its URLs, without waiting for a new release.
+    #[arg(
+        long,
+        global = true,
+        value_name = "FILE",
+        env = "EXTRACT_WISH_URL_GAMES",
+        help = "Load game definitions from this file, in the format of games.json, over the built-in ones"
+    )]
+    games: Option<PathBuf>,
+    #[arg(
+        short,
+        long,
+        global = true,
+        value_name = "N",
+        value_parser = clap::value_parser!(u16).range(1..),
+        default_value_t = extractor::DEFAULT_PARALLELISM as u16,
+        help = "Check at most N URLs with the API at the same time"
+    )]
+    jobs: u16,
+    #[arg(short, long, global = true, help = "Print debug logs")]
+    verbose: bool,
+}
 
-        Ok(Self {
-            install_path: install_path.to_path_buf(),
-            game_type,
-        })
-    }
+// Where to look for the URLs.
+#[derive(Args)]
+struct SourceArgs {
+    #[arg(help = "The game install directory. Found in the game logs when not given")]
+    install_path: Option<PathBuf>,
+    #[arg(
+        long,
+        value_name = "N",
+        value_parser = clap::value_parser!(u16).range(1..),
+        conflicts_with = "install_path",
+        help = "Use the Nth install found in the game logs, as listed by doctor, instead of the most recently played one"
+    )]
+    install: Option<u16>,
+    #[arg(
+        long,
+        value_name = "FILE",
+        conflicts_with_all = ["install_path", "install"],
+        help = "Search this data_2 cache file instead of a game install"
+    )]
+    data2: Option<PathBuf>,
+}
 
-    pub fn extract_url(&self) -> Result<String> {
-        const WEB_CACHE_DIR_NAME: &str = "webCaches";
-        let web_cache_dir = self
-            .install_path
-            .join(self.game_type.data_dir_name)
-            .join(WEB_CACHE_DIR_NAME);
-        if !web_cache_dir.is_dir() {
-            return Err(anyhow::anyhow!(
-                "{} is not a directory",
-                web_cache_dir.display()
-            ));
-        }
+#[derive(Args)]
+struct ExtractArgs {
+    #[command(flatten)]
+    source: SourceArgs,
+    #[arg(
+        long,
+        help = "Do not check the URLs with the API, e.g. without network. The URLs may have expired"
+    )]
+    no_validate: bool,
+    #[arg(long, help = "List every URL in the cache, not only the best one")]
+    all: bool,
+    #[arg(
+        long,
+        help = "Print a JSON report with every URL in the cache, instead of text"
+    )]
+    json: bool,
+    #[arg(
+        long,
+        conflicts_with_all = ["install_path", "install", "data2"],
+        help = "Find the URL of every install found in the game logs, not only of one"
+    )]
+    all_installs: bool,
+}
 
-        let data2_path = get_to_data2_file(&web_cache_dir).context("Failed to find data_2 file")?;
-
-        let content = fs::read(data2_path).context("Failed to read data_2 file")?;
-        let urls = find_gacha_urls_in_slice(
-            &content,
-            self.game_type.marker,
-            self.game_type.url_start,
-            self.game_type.url_end,
-        )?;
-        if urls.is_empty() {
-            bail!("Found no gacha URLs");
-        }
+#[derive(Args)]
+struct ValidateArgs {
+    #[arg(help = "The gacha URLs. Read from stdin, one per line, when not given or \"-\"")]
+    urls: Vec<String>,
+    #[arg(long, help = "Print a JSON report instead of text")]
+    json: bool,
+}
 
-        for url in urls {
-            let result = (self.game_type.valid_url_check_fn)(&url);
-            match result {
-                Ok(url) => return Ok(url),
-                Err(e) => {
-                    log::debug!("Testing {} returned an error: {}", url, e);
-                    continue;
-                }
-            }
-        }
+#[derive(Args)]
+struct ScanArgs {
+    #[arg(help = "The files to scan. Reads stdin when not given or \"-\"")]
+    files: Vec<PathBuf>,
+    #[arg(long, help = "Check whether the URLs work")]
+    validate: bool,
+    #[arg(long, help = "Print a JSON report instead of text")]
+    json: bool,
+}
 
-        bail!("Failed to find a working gacha URL. Check the gacha logs in game first.")
-    }
+#[derive(Args)]
+struct WatchArgs {
+    #[arg(help = "The game install directory. Found in the game logs when not given")]
+    install_path: Option<PathBuf>,
+    #[arg(
+        long,
+        value_name = "N",
+        value_parser = clap::value_parser!(u16).range(1..),
+        conflicts_with = "install_path",
+        help = "Use the Nth install found in the game logs, as listed by doctor, instead of the most recently played one"
+    )]
+    install: Option<u16>,
+    #[arg(
+        long,
+        help = "Do not check the URLs with the API. The URLs may have expired"
+    )]
+    no_validate: bool,
+    #[arg(
+        long,
+        help = "Print a JSON report on a line for each new URL, instead of text"
+    )]
+    json: bool,
+    #[arg(
+        long,
+        help = "Scan the cache every few seconds instead of being notified, e.g. on network file systems"
+    )]
+    poll: bool,
 }
 
-fn filename_to_version(filename: &str) -> Option<Version> {
-    let parts = filename.split('.').collect::<Vec<&str>>();
-    if parts.len() != 4 {
-        return None;
-    }
-    let major = parts[0].parse::<u32>().ok()?;
-    let minor = parts[1].parse::<u32>().ok()?;
-    let patch = parts[2].parse::<u32>().ok()?;
-    let other = parts[3].parse::<u32>().ok()?;
-    Some(Version {
-        major,
-        minor,
-        patch,
-        other,
-    })
+// Where the gacha history comes from.
+#[derive(Args)]
+struct HistoryArgs {
+    #[command(flatten)]
+    source: SourceArgs,
+    #[arg(
+        long,
+        value_name = "STORE",
+        help = "Keep the history in this file, and only fetch the pulls that are not in it yet"
+    )]
+    sync: Option<PathBuf>,
 }
 
-fn collect_versioned_directories(path: &Path) -> Vec<VersionedDirectory> {
-    let files = fs::read_dir(path);
-    if files.is_err() {
-        return vec![];
-    }
+#[derive(Args)]
+#[group(id = "format", required = true, multiple = true)]
+struct ExportArgs {
+    #[arg(long, value_name = "FILE", help = "Save the history as UIGF v4")]
+    uigf: Option<PathBuf>,
+    #[arg(
+        long,
+        value_name = "FILE",
+        requires = "uigf",
+        help = "JSON object from the English item names to their ids, for the UIGF export of Genshin"
+    )]
+    item_ids: Option<PathBuf>,
+    #[arg(
+        long,
+        value_name = "FILE",
+        help = "Save the history as CSV, one row per pull"
+    )]
+    csv: Option<PathBuf>,
+    #[cfg(feature = "xlsx")]
+    #[arg(
+        long,
+        value_name = "FILE",
+        help = "Save the history as XLSX, one sheet per banner"
+    )]
+    xlsx: Option<PathBuf>,
+}
 
-    let files = files.unwrap();
+#[derive(Subcommand)]
+enum Command {
+    #[command(about = "Find the newest gacha URL that works (default)")]
+    Extract(ExtractArgs),
+    #[command(about = "Check whether gacha URLs work, and tell their games")]
+    Validate(ValidateArgs),
+    #[command(about = "Find gacha URLs of any game in files, e.g. HAR exports or memory dumps")]
+    Scan(ScanArgs),
+    #[command(about = "Fetch the gacha history of every banner")]
+    Fetch {
+        #[command(flatten)]
+        history: HistoryArgs,
+        #[arg(short, long, value_name = "FILE", help = "Save the history as JSON")]
+        output: Option<PathBuf>,
+    },
+    #[command(about = "Fetch the gacha history and save it for trackers and spreadsheets")]
+    Export {
+        #[command(flatten)]
+        history: HistoryArgs,
+        #[command(flatten)]
+        formats: ExportArgs,
+    },
+    #[command(about = "Print the pity of each banner, the 50/50 results and the averages")]
+    Stats {
+        #[command(flatten)]
+        history: HistoryArgs,
+        #[arg(short, long, value_name = "FILE", help = "Save the statistics as JSON")]
+        output: Option<PathBuf>,
+    },
+    #[command(about = "Watch the cache, and print the new URL each time it changes")]
+    Watch(WatchArgs),
+    #[command(about = "Check each step of finding the URL, and tell what is wrong")]
+    Doctor {
+        #[command(flatten)]
+        source: SourceArgs,
+        #[arg(long, help = "Do not check the URLs with the API")]
+        no_validate: bool,
+    },
+}
 
-    files
-        .filter_map(|file| {
-            let file = file.ok()?;
-            let filename = file.file_name();
-            let filename_str = filename.to_str()?;
+// Failures of the CLI itself, as opposed to the ones of the library.
+#[derive(Debug, thiserror::Error)]
+enum CliError {
+    #[error("Failed to find the game install directory. Pass the path to the install directory.")]
+    NoInstall,
+    // The install chosen with --install, and the number of installs found.
+    #[error("There is no install {0}, found {1}. Run doctor to list them.")]
+    NoSuchInstall(u16, usize),
+    #[error("{0} does not exist")]
+    PathNotFound(PathBuf),
+    #[error("{0:?} does not match any game")]
+    UnknownGame(String),
+    #[error("Found no URL to validate")]
+    NoUrlGiven,
+}
 
-            let version = filename_to_version(filename_str)?;
-            let versioned_directory = VersionedDirectory {
-                path: path.join(filename_str).to_path_buf(),
-                version,
-            };
-            Some(versioned_directory)
-        })
-        .collect()
+// Loads the built-in game definitions and the ones in |args.games|, keeping only the ones that
+// |args.game| names if given.
+fn load_registry(args: &GlobalArgs) -> Result<GameRegistry> {
+    let mut registry = GameRegistry::builtin();
+    if let Some(definitions_path) = &args.games {
+        registry.merge(GameRegistry::load(definitions_path)?);
+    }
+    let Some(game) = args.game.as_deref() else {
+        return Ok(registry);
+    };
+    let selected = registry.select(game);
+    if selected.games.is_empty() {
+        return Err(CliError::UnknownGame(game.to_string()).into());
+    }
+    Ok(selected)
 }
 
-const RELATIVE_PATH_TO_DATA2: &[&str] = &["Cache", "Cache_Data", "data_2"];
+// Opens the extractor for |source|, which checks at most |jobs| URLs at the same time.
+fn open_extractor(source: &SourceArgs, registry: GameRegistry, jobs: u16) -> Result<PullExtractor> {
+    if let Some(data2_path) = &source.data2 {
+        return Ok(
+            PullExtractor::from_data2_file(data2_path, registry).with_parallelism(jobs.into())
+        );
+    }
+    let install_path = match &source.install_path {
+        Some(install_path) => install_path.clone(),
+        None => choose_install(&registry, source.install)?,
+    };
+    if !install_path.exists() {
+        return Err(CliError::PathNotFound(install_path).into());
+    }
+    Ok(PullExtractor::with_registry(&install_path, registry)?.with_parallelism(jobs.into()))
+}
 
-fn get_to_data2_file(web_cache_dir: &Path) -> Option<PathBuf> {
-    let mut versioned_dirs = collect_versioned_directories(web_cache_dir);
+// Finds a working URL and prints it.
+fn extract_url(extractor: &PullExtractor) -> Result<ExtractedUrl> {
+    let extracted = extractor
+        .extract_url()
+        .context("Failed to find gacha URL")?;
+    if let Some(timestamp) = extracted.timestamp {
+        println!("The URL was last used at {}", format_timestamp(timestamp));
+    }
+    println!("The URL was found in {}", extracted.source_dir.display());
+    println!("Found gacha URL! Copy the URL below:");
+    println!("{}", extracted.url);
+    Ok(extracted)
+}
 
-    if versioned_dirs.is_empty() {
-        println!("Failed to find any versioned directories");
-        return None;
+// Fetches the gacha history with a working URL. With |store_path|, only the new records are
+// fetched, and the whole stored history of the account is returned.
+fn load_history(
+    args: &HistoryArgs,
+    registry: GameRegistry,
+    jobs: u16,
+) -> Result<(ExtractedUrl, history::GachaHistory)> {
+    let extractor = open_extractor(&args.source, registry, jobs)?;
+    let extracted = extract_url(&extractor)?;
+    // The history is fetched with the client that validated the URL.
+    let client = extractor.http_client();
+    let history = match &args.sync {
+        Some(store_path) => sync_history(client, &extracted, store_path)?,
+        None => {
+            println!("Fetching the gacha history...");
+            history::fetch_history(client, &extracted.game_type, &extracted.url)?
+        }
+    };
+    for banner in &history.banners {
+        println!("{}: {} pulls", banner.name, banner.records.len());
     }
+    Ok((extracted, history))
+}
 
-    // Note that this is descending order, i.e. the biggest version is at the front.
-    // The latest gacha info is in the latest webcache dir.
-    versioned_dirs.sort_by(|a, b| b.version.cmp(&a.version));
-    let latest_version_dir = &versioned_dirs[0].path;
+// Fetches the records that are not in the store at |store_path| yet, and saves them to the store.
+// Returns the whole stored history of the account.
+fn sync_history(
+    client: &dyn HttpClient,
+    extracted: &ExtractedUrl,
+    store_path: &Path,
+) -> Result<history::GachaHistory> {
+    let mut store = store::HistoryStore::load(store_path)?;
+    let game_biz = history::game_biz(&extracted.url).context("URL does not have game_biz")?;
+    let known_ids = store.known_ids(&game_biz);
+
+    println!("Fetching the new gacha history...");
+    let new_history =
+        history::fetch_new_history(client, &extracted.game_type, &extracted.url, |record| {
+            known_ids.contains(&(record.uid.clone(), record.id.clone()))
+        })?;
+    let uid = new_history.uid.clone();
+    let added = store.merge(new_history);
+    store.save(store_path)?;
+
+    let account = store
+        .account(&game_biz, &uid)
+        .cloned()
+        .context("Found no gacha history for the account")?;
+    println!(
+        "Added {} new pulls to {}, {} pulls in total",
+        added,
+        store_path.display(),
+        account.record_count()
+    );
+    Ok(account)
+}
 
-    let data2_path = latest_version_dir.join(RELATIVE_PATH_TO_DATA2.iter().collect::<PathBuf>());
-    if !data2_path.is_file() {
-        return None;
+// Returns the path of the |choice|th install found in the game logs, 1 based, or of the most
+// recently played one.
+fn choose_install(registry: &GameRegistry, choice: Option<u16>) -> Result<PathBuf> {
+    let installs = discover::find_default_installs(registry);
+    if installs.is_empty() {
+        return Err(CliError::NoInstall.into());
     }
-    Some(data2_path)
+    let index = usize::from(choice.unwrap_or(1)) - 1;
+    let install = installs
+        .get(index)
+        .ok_or_else(|| CliError::NoSuchInstall(choice.unwrap_or(1), installs.len()))?;
+    // Printed to stderr, so that stdout only has the result, e.g. the JSON report.
+    eprintln!("Using [{}] {}", index + 1, describe_install(install));
+    if choice.is_none() && installs.len() > 1 {
+        eprintln!("Found other installs. Pass --install <N> to use one of them:");
+        for (i, other) in installs.iter().enumerate().skip(1) {
+            eprintln!("  [{}] {}", i + 1, describe_install(other));
+        }
+    }
+    Ok(install.install_path.clone())
 }
 
-fn find_gacha_urls_in_slice(
-    content: &[u8],
-    marker: &str,
-    url_start: &str,
-    end_marker: &str,
-) -> Result<Vec<String>> {
-    let mut urls = vec![];
-    for marker_match in content.find_iter(marker) {
-        let gacha_marker_end = marker_match + marker.len();
-        let rest_of_content = &content[gacha_marker_end..];
+fn describe_install(install: &discover::DiscoveredInstall) -> String {
+    let mut description = format!(
+        "{} ({}) in {}",
+        install.game,
+        install.regions.iter().join(", "),
+        install.install_path.display()
+    );
+    if let Some(wine_prefix) = &install.wine_prefix {
+        description += &format!(", Wine prefix {}", wine_prefix.display());
+    }
+    description
+}
+
+fn format_timestamp(timestamp: SystemTime) -> String {
+    DateTime::<Local>::from(timestamp)
+        .format("%Y-%m-%d %H:%M:%S %:z")
+        .to_string()
+}
 
-        // Note that this variable contains the index from the beginning of |content|.
-        let url_end_pos = rest_of_content
-            .find(end_marker)
-            .context("Failed to find end marker")?
-            + end_marker.len()
-            + gacha_marker_end;
+fn format_last_used(extracted: &ExtractedUrl) -> String {
+    extracted
+        .timestamp
+        .map_or_else(|| "unknown".to_string(), format_timestamp)
+}
+
+fn extract(args: &ExtractArgs, registry: GameRegistry, jobs: u16) -> Result<()> {
+    if args.all_installs {
+        return extract_all_installs(args, registry, jobs);
+    }
+    if args.json {
+        let (report, result) = extraction_report(args, &args.source, registry, jobs);
+        println!("{}", serde_json::to_string_pretty(&report)?);
+        return result;
+    }
+    let extractor = open_extractor(&args.source, registry, jobs)?;
+    print_urls(args, &extractor)
+}
 
-        // Since URLs can only be a certain length, the value in this variable is used to slice
-        // |content| to find the beginning of the URL.
-        let url_search_start_pos = url_end_pos.saturating_sub(MAX_URL_LENGTH);
+// Does what extract does for each install found in the game logs. With --json, the reports are
+// printed as one JSON array. Like extract --all, this fails only if none of the installs has a
+// URL.
+fn extract_all_installs(args: &ExtractArgs, registry: GameRegistry, jobs: u16) -> Result<()> {
+    let installs = discover::find_default_installs(&registry);
+    if installs.is_empty() {
+        return Err(CliError::NoInstall.into());
+    }
+    let mut reports = vec![];
+    let mut errors = vec![];
+    for (i, install) in installs.iter().enumerate() {
+        let source = SourceArgs {
+            install_path: Some(install.install_path.clone()),
+            install: None,
+            data2: None,
+        };
+        let result = if args.json {
+            let (report, result) = extraction_report(args, &source, registry.clone(), jobs);
+            reports.push(report);
+            result
+        } else {
+            if i > 0 {
+                println!();
+            }
+            println!("[{}] {}", i + 1, describe_install(install));
+            let result = open_extractor(&source, registry.clone(), jobs)
+                .and_then(|extractor| print_urls(args, &extractor));
+            if let Err(error) = &result {
+                println!("Failed: {:#}", error);
+            }
+            result
+        };
+        if let Err(error) = result {
+            errors.push(error);
+        }
+    }
+    if args.json {
+        println!("{}", serde_json::to_string_pretty(&reports)?);
+    }
+    if errors.len() == installs.len() {
+        // The most recently played install is the most relevant one.
+        return Err(errors
+            .remove(0)
+            .context("Found no gacha URL in any install"));
+    }
+    Ok(())
+}
 
-        let potential_url_slice = &content[url_search_start_pos..url_end_pos];
+// Prints the URLs of |extractor| as |args| asks for.
+fn print_urls(args: &ExtractArgs, extractor: &PullExtractor) -> Result<()> {
+    match (args.no_validate, args.all) {
+        (false, false) => extract_url(extractor).map(|_| ()),
+        (false, true) => print_checked_urls(extractor),
+        (true, all) => print_unverified_urls(extractor, all),
+    }
+}
 
-        // Although there could be multiple URLs in the slice, since the slice ends
-        // with the end marker, the last occurrence of the url start marker must
-        // be the start of the URL.
-        let url_start_pos = potential_url_slice
-            .rfind(url_start)
-            .context("Failed to find url start")?;
+// Prints the URLs in the cache without checking them, best first. Only the best one unless
+// |all|.
+fn print_unverified_urls(extractor: &PullExtractor, all: bool) -> Result<()> {
+    let mut urls = extractor
+        .extract_unverified_urls()
+        .context("Failed to find gacha URL")?;
+    if all {
+        println!(
+            "Found {} gacha URLs. They were NOT checked, so they may have expired. The first one is the most likely to work.",
+            urls.len()
+        );
+    } else {
+        urls.truncate(1);
+        println!("Found gacha URL. It was NOT checked, so it may have expired.");
+    }
+    for (i, extracted) in urls.iter().enumerate() {
+        println!();
+        println!(
+            "[{}] UNVERIFIED, last used at {}",
+            i + 1,
+            format_last_used(extracted)
+        );
+        println!("{}", extracted.url);
+    }
+    Ok(())
+}
 
-        urls.push(
-            String::from_utf8(potential_url_slice[url_start_pos..].to_vec())
-                .context("Failed to convert URL to string")?,
+// Checks all the URLs in the cache, and prints them best first with the results.
+fn print_checked_urls(extractor: &PullExtractor) -> Result<()> {
+    let checked = extractor
+        .check_all_urls()
+        .context("Failed to find gacha URL")?;
+    println!("Found {} gacha URLs.", checked.len());
+    for (i, checked) in checked.iter().enumerate() {
+        println!();
+        let status = match &checked.error {
+            None => "WORKS".to_string(),
+            Some(error) => format!("FAILED ({})", error),
+        };
+        println!(
+            "[{}] {}, last used at {}",
+            i + 1,
+            status,
+            format_last_used(&checked.extracted)
         );
+        println!("{}", checked.extracted.url);
     }
-    Ok(urls)
-}
-
-fn test_genshin_wish_url(url: &str, api_host: &str) -> Result<String> {
-    log::debug!("Checking genshin wish url: {}", url);
-    let client = Client::new();
-    let mut uri =
-        reqwest::Url::parse(url).with_context(|| format!("{} is not a valid URL", url))?;
-
-    uri.set_path("gacha_info/api/getGachaLog");
-    uri.set_host(Some(api_host))
-        .with_context(|| format!("Failed to set host to {}", api_host))?;
-    uri.set_fragment(None);
-
-    let mut query_params: HashMap<Cow<str>, Cow<str>> = uri.query_pairs().collect();
-    query_params.insert("lang".into(), "en".into());
-    query_params.insert("gacha_type".into(), "301".into());
-    query_params.insert("size".into(), "5".into());
-    query_params.insert("lang".into(), "en-us".into());
-
-    // Sort the parameters, to make testing easier.
-    let query_params = query_params
-        .keys()
-        .sorted()
-        .map(|key| (key, query_params.get(key).unwrap()))
-        .collect_vec();
-
-    uri.set_query(Some(
-        &serde_urlencoded::to_string(&query_params).context("Failed to set query params")?,
-    ));
-
-    let response = client
-        .get(uri.as_str())
-        .header("Content-Type", "application/json")
-        .send()?
-        .json::<Value>()?;
-
-    let retcode = response
-        .get("retcode")
-        .context("Failed to find retcode in response JSON")?;
-
-    log::debug!("Got retcode: {}", retcode);
-
-    let retcode = retcode
-        .as_i64()
-        .context("Failed to convert retcode to i64")?;
-    if retcode == 0 {
-        Ok(url.to_string())
-    } else {
-        bail!("JSON retcode did not contain 0, it was {}", retcode)
+    if checked.iter().any(|checked| checked.extracted.verified) {
+        return Ok(());
     }
+    Err(no_working_url(checked))
 }
 
-// TODO: A test with dependency injection would be good.
-fn test_zzz_signal_url(url: &str) -> Result<String> {
-    log::debug!("Checking zzz signal url: {}", url);
+fn no_working_url(checked: Vec<CheckedUrl>) -> anyhow::Error {
+    let errors = checked
+        .into_iter()
+        .filter_map(|checked| checked.error)
+        .collect();
+    anyhow::Error::from(Error::NoWorkingUrl(errors)).context("Failed to find gacha URL")
+}
 
-    // A hack to get localhost url to always use HTTP. Only good for testing.
-    let mut parsed_url = Url::parse(url).context("Failed to parse URL")?;
-    if parsed_url.scheme() == "https" && parsed_url.host_str() == Some("127.0.0.1") {
-        parsed_url
-            .set_scheme("http")
-            .map_err(|_| anyhow!("Failed to change scheme to http"))?;
+// Returns a report of every URL in |source|, and of the one to use. On failures, the report has
+// the error too, so that it can be printed anyway.
+fn extraction_report(
+    args: &ExtractArgs,
+    source: &SourceArgs,
+    registry: GameRegistry,
+    jobs: u16,
+) -> (ExtractionReport, Result<()>) {
+    let mut report = ExtractionReport::default();
+    let result = open_extractor(source, registry, jobs).and_then(|extractor| {
+        report = ExtractionReport::new(&extractor);
+        fill_report(&mut report, &extractor, args.no_validate)
+    });
+    if let Err(error) = &result {
+        report.set_error(error);
     }
+    (report, result)
+}
 
-    let client = Client::new();
-    let response = client
-        .get(parsed_url.clone())
-        .header("Content-Type", "application/json")
-        .send()
-        .context("Failed to get response")?
-        .json::<Value>()
-        .context("Failed to get json response")?;
-
-    const RETURN_CODE_FIELD_NAME: &str = "retcode";
-    let retcode = response.get(RETURN_CODE_FIELD_NAME).context(format!(
-        "Response JSON from {} did not contain a {} field",
-        parsed_url, RETURN_CODE_FIELD_NAME
-    ))?;
+fn fill_report(
+    report: &mut ExtractionReport,
+    extractor: &PullExtractor,
+    no_validate: bool,
+) -> Result<()> {
+    if no_validate {
+        let urls = extractor
+            .extract_unverified_urls()
+            .context("Failed to find gacha URL")?;
+        report.add_unverified_urls(&urls);
+        return Ok(());
+    }
+    let checked = extractor
+        .check_all_urls()
+        .context("Failed to find gacha URL")?;
+    report.add_checked_urls(&checked);
+    if report.url.is_none() {
+        return Err(no_working_url(checked));
+    }
+    Ok(())
+}
 
-    log::debug!("{} contained: {}", RETURN_CODE_FIELD_NAME, retcode);
+// Like fill_report(), but only with the URLs that are not in |checked_authkeys|. Nothing is chosen
+// if there are no new URLs.
+fn fill_report_with_new_urls(
+    report: &mut ExtractionReport,
+    extractor: &PullExtractor,
+    checked_authkeys: &mut HashSet<String>,
+) -> Result<()> {
+    let checked = extractor
+        .check_new_urls(checked_authkeys)
+        .context("Failed to find gacha URL")?;
+    report.add_checked_urls(&checked);
+    if report.url.is_none() && !checked.is_empty() {
+        return Err(no_working_url(checked));
+    }
+    Ok(())
+}
 
-    let retcode = retcode.as_i64().context("Not a number.")?;
-    if retcode != 0 {
-        bail!("Got non-zero return code: {}", retcode);
+// Checks the URLs in |args|, each with the validator of its game, at most |jobs| at the same
+// time. Fails with the error of the first URL that does not work.
+fn validate(args: &ValidateArgs, registry: GameRegistry, jobs: u16) -> Result<()> {
+    let urls = if args.urls.is_empty() || args.urls == ["-"] {
+        io::stdin()
+            .lines()
+            .map_ok(|line| line.trim().to_string())
+            .filter_ok(|line| !line.is_empty())
+            .collect::<io::Result<Vec<_>>>()
+            .context("Failed to read the URLs from stdin")?
+    } else {
+        args.urls.clone()
+    };
+    if urls.is_empty() {
+        return Err(CliError::NoUrlGiven.into());
     }
 
-    // Recreate parsed_url from original URL again, so that it would be unmodified even for tests.
-    let parsed_url = Url::parse(url).context("Failed to parse URL")?;
-    let mut query_params: Vec<(String, String)> = parsed_url.query_pairs().into_owned().collect();
-    const KEYS_TO_KEEP: [&str; 5] = ["authkey", "authkey_ver", "sign_type", "game_biz", "lang"];
-    query_params.retain(|(key, _)| KEYS_TO_KEEP.contains(&key.as_str()));
+    // The game is told by game_biz, so no install directory is needed.
+    let game_types = urls
+        .iter()
+        .map(|url| registry.find_by_url(url))
+        .collect::<Vec<_>>();
+    let known_urls = urls
+        .iter()
+        .zip(&game_types)
+        .filter_map(|(url, game_type)| Some((&game_type.as_ref()?.validation, url.as_str())))
+        .collect::<Vec<_>>();
+    let mut results =
+        validate::validate_all(&reqwest::blocking::Client::new(), &known_urls, jobs.into())
+            .into_iter();
+
+    let mut reports = vec![];
+    let mut first_error = None;
+    for (url, game_type) in urls.iter().zip(game_types) {
+        let result = match game_type {
+            Some(_) => results.next().expect("Every known URL is validated"),
+            None => Err(Error::InvalidUrl {
+                url: url.clone(),
+                reason: "game_biz is missing or is not of a known game".to_string(),
+            }),
+        };
+        reports.push(ValidationReport::new(url, game_type, &result));
+        if let Err(error) = result {
+            first_error.get_or_insert(error);
+        }
+    }
 
-    Ok(format!(
-        "{}://{}{}?{}",
-        parsed_url.scheme(),
-        parsed_url
-            .host()
-            .context(format!("Cannot find host in URL: {}", parsed_url))?,
-        parsed_url.path(),
-        serde_urlencoded::to_string(&query_params)
-            .with_context(|| format!("Failed to serialize query params {:?}", &query_params))?
-    ))
+    if args.json {
+        println!("{}", serde_json::to_string_pretty(&reports)?);
+    } else {
+        for (i, report) in reports.iter().enumerate() {
+            if i > 0 {
+                println!();
+            }
+            let game = match (&report.game, report.region) {
+                (Some(game), Some(region)) => format!("{} ({})", game, region),
+                _ => "unknown game".to_string(),
+            };
+            match &report.error {
+                None => println!("WORKS, {}", game),
+                Some(error) => println!("FAILED ({}), {}", error, game),
+            }
+            println!("{}", report.url);
+        }
+    }
+    match first_error {
+        Some(error) => Err(anyhow::Error::from(error).context("The URL does not work")),
+        None => Ok(()),
+    }
 }
 
-fn main() -> Result<()> {
-    env_logger::init();
-
-    let args: Vec<String> = env::args().collect();
-    if args.len() != 2 {
-        println!("Usage: {} <path to genshin install directory>", args[0]);
-        std::process::exit(1);
+// Prints the gacha URLs in the files of |args|, with the game that each one is for. With
+// --validate, at most |jobs| URLs are checked at the same time.
+fn scan(args: &ScanArgs, registry: GameRegistry, jobs: u16) -> Result<()> {
+    let stdin_path = Path::new("-");
+    let files = if args.files.is_empty() {
+        vec![stdin_path.to_path_buf()]
+    } else {
+        args.files.clone()
+    };
+
+    let mut found_urls = vec![];
+    for file in &files {
+        let content = if file == stdin_path {
+            let mut content = vec![];
+            io::stdin()
+                .read_to_end(&mut content)
+                .context("Failed to read stdin")?;
+            content
+        } else {
+            fs::read(file).with_context(|| format!("Failed to read {}", file.display()))?
+        };
+        found_urls.extend(
+            extractor::scan_for_urls(&content, &registry.games)
+                .into_iter()
+                .map(|found| (file, found)),
+        );
     }
-    let path = Path::new(args[1].as_str());
-    if !path.exists() {
-        println!("{} does not exist", path.display());
-        std::process::exit(1);
+
+    let results = if args.validate {
+        let urls = found_urls
+            .iter()
+            .map(|(_, found)| (&found.game_type.validation, found.url.as_str()))
+            .collect::<Vec<_>>();
+        validate::validate_all(&reqwest::blocking::Client::new(), &urls, jobs.into())
+            .into_iter()
+            .map(Some)
+            .collect()
+    } else {
+        found_urls.iter().map(|_| None).collect::<Vec<_>>()
+    };
+    let mut reports = vec![];
+    let mut errors = vec![];
+    for ((file, found), result) in found_urls.into_iter().zip(results) {
+        let report = match result {
+            Some(result) => {
+                let report = ValidationReport::new(&found.url, Some(found.game_type), &result);
+                errors.extend(result.err());
+                report
+            }
+            None => {
+                let url = found
+                    .game_type
+                    .validation
+                    .normalize(&found.url)
+                    .unwrap_or_else(|_| found.url.clone());
+                ValidationReport::unverified(&url, Some(found.game_type))
+            }
+        };
+        reports.push(ScanReport {
+            source: file.clone(),
+            validation: report,
+        });
     }
 
-    let extractor = PullExtractor::new(path)?;
-    let result = extractor.extract_url();
-    if let Ok(url) = result {
-        println!("Found gacha URL! Copy the URL below:");
-        println!("{}", url);
+    if args.json {
+        println!("{}", serde_json::to_string_pretty(&reports)?);
     } else {
+        for (i, report) in reports.iter().enumerate() {
+            if i > 0 {
+                println!();
+            }
+            let validation = &report.validation;
+            let status = match (&validation.status, &validation.error) {
+                (CandidateStatus::Works, _) => "WORKS".to_string(),
+                (CandidateStatus::Failed, Some(error)) => format!("FAILED ({})", error),
+                _ => "UNVERIFIED".to_string(),
+            };
+            let source = if report.source == stdin_path {
+                "stdin".to_string()
+            } else {
+                report.source.display().to_string()
+            };
+            println!(
+                "[{}] {}, {} ({}), in {}",
+                i + 1,
+                status,
+                validation.game.as_deref().unwrap_or_default(),
+                validation
+                    .region
+                    .map(|region| region.to_string())
+                    .unwrap_or_default(),
+                source
+            );
+            println!("{}", validation.url);
+        }
+    }
+
+    if reports.is_empty() {
+        return Err(Error::NoUrlsFound.into());
+    }
+    // Like extract --all, the scan fails only if none of the URLs works.
+    let any_works = reports
+        .iter()
+        .any(|report| report.validation.status == CandidateStatus::Works);
+    if args.validate && !any_works {
+        return Err(Error::NoWorkingUrl(errors).into());
+    }
+    Ok(())
+}
+
+fn fetch(
+    args: &HistoryArgs,
+    output: Option<&Path>,
+    registry: GameRegistry,
+    jobs: u16,
+) -> Result<()> {
+    let (_, history) = load_history(args, registry, jobs)?;
+    if let Some(output) = output {
+        write_json(output, &history)?;
         println!(
-            "Failed to find gacha URL with error: {}",
-            result.unwrap_err()
+            "Saved {} pulls to {}",
+            history.record_count(),
+            output.display()
         );
     }
+    Ok(())
+}
 
+fn export(
+    args: &HistoryArgs,
+    formats: &ExportArgs,
+    registry: GameRegistry,
+    jobs: u16,
+) -> Result<()> {
+    let item_ids = match &formats.item_ids {
+        Some(path) => uigf::load_item_ids(path)?,
+        None => uigf::ItemIds::new(),
+    };
+    let (_, history) = load_history(args, registry, jobs)?;
+    if let Some(uigf_output) = &formats.uigf {
+        let uigf = uigf::Uigf::from_history(&history, Utc::now().timestamp(), &item_ids)
+            .with_context(|| {
+                if formats.item_ids.is_none() && history.game_biz.starts_with("hk4e") {
+                    "Failed to convert to UIGF. Genshin's API does not return the item ids, pass \
+                     them with --item-ids"
+                } else {
+                    "Failed to convert to UIGF"
+                }
+            })?;
+        write_json(uigf_output, &uigf)?;
+        println!("Saved UIGF to {}", uigf_output.display());
+    }
+    if let Some(csv_output) = &formats.csv {
+        let file = fs::File::create(csv_output)
+            .with_context(|| format!("Failed to create {}", csv_output.display()))?;
+        export::write_csv(&history, file)?;
+        println!("Saved CSV to {}", csv_output.display());
+    }
+    #[cfg(feature = "xlsx")]
+    if let Some(xlsx_output) = &formats.xlsx {
+        export::write_xlsx(&history, xlsx_output)?;
+        println!("Saved XLSX to {}", xlsx_output.display());
+    }
     Ok(())
 }
 
-#[cfg(test)]
-mod tests {
-    use std::io::{BufWriter, Write};
+fn print_stats(
+    args: &HistoryArgs,
+    output: Option<&Path>,
+    registry: GameRegistry,
+    jobs: u16,
+) -> Result<()> {
+    let (extracted, history) = load_history(args, registry, jobs)?;
+    let stats = stats::HistoryStats::new(&history, &extracted.game_type);
+    println!();
+    print!("{}", stats);
+    if let Some(output) = output {
+        write_json(output, &stats)?;
+        println!("Saved statistics to {}", output.display());
+    }
+    Ok(())
+}
 
-    use super::*;
-    use tempfile::tempdir;
+// Prints the URL whenever the cache changes and has a new one, until killed.
+fn watch(args: &WatchArgs, registry: GameRegistry, jobs: u16) -> Result<()> {
+    let source = SourceArgs {
+        install_path: args.install_path.clone(),
+        install: args.install,
+        data2: None,
+    };
+    let extractor = open_extractor(&source, registry, jobs)?;
+    let web_cache_dir = extractor
+        .web_cache_dir()
+        .expect("Install directory has a web cache directory");
+    if !web_cache_dir.is_dir() {
+        return Err(Error::WebCacheDirNotFound(web_cache_dir).into());
+    }
+    let watcher = if args.poll {
+        CacheWatcher::polling(&web_cache_dir, watch::DEFAULT_POLL_INTERVAL)?
+    } else {
+        CacheWatcher::new(&web_cache_dir)?
+    };
+    eprintln!(
+        "Watching {}. Open the gacha history in game to get a new URL.",
+        web_cache_dir.display()
+    );
+
+    let mut last_url = None;
+    let mut checked_authkeys = HashSet::new();
+    loop {
+        print_new_url(&extractor, args, &mut last_url, &mut checked_authkeys)?;
+        watcher.wait_for_change()?;
+    }
+}
 
-    // Verify it can find the URL in binary data.
-    #[test]
-    fn find_gacha_url_only_url() {
-        let test_url = "https://gs.hoyoverse.com/genshin/event/e20190909gacha-v3/index.html?anythinghere&game_biz=hk4e_global";
-
-        // any data.
-        let mut test_data: Vec<u8> = vec![2, 8, 11, 22, 93];
-        test_data.extend_from_slice(test_url.as_bytes());
-        // More irrelevant data at end.
-        test_data.extend_from_slice(&[43, 100, 65, 2, 1, 4, 73]);
-
-        let result = find_gacha_urls_in_slice(
-            &test_data,
-            "gacha-v3",
-            "https://gs.hoyoverse.com/",
-            "game_biz=hk4e_global",
-        );
-        assert!(result.is_ok());
-        let result = result.unwrap();
-        assert_eq!(1, result.len());
-        assert_eq!(test_url, result[0]);
+// Extracts the URL, and prints it if it is not |last_url|. The failures are only printed, since a
+// later change of the cache can fix them. The JSON report only has the URLs whose authkeys are not
+// in |checked_authkeys| yet, so that each change of the cache does not check every URL again.
+fn print_new_url(
+    extractor: &PullExtractor,
+    args: &WatchArgs,
+    last_url: &mut Option<String>,
+    checked_authkeys: &mut HashSet<String>,
+) -> Result<()> {
+    if args.json {
+        let mut report = ExtractionReport::new(extractor);
+        let result = if args.no_validate {
+            fill_report(&mut report, extractor, true)
+        } else {
+            fill_report_with_new_urls(&mut report, extractor, checked_authkeys)
+        };
+        if let Err(error) = result {
+            eprintln!("{:#}", error);
+            return Ok(());
+        }
+        if report.url.is_some() && report.url != *last_url {
+            *last_url = report.url.clone();
+            println!("{}", serde_json::to_string(&report)?);
+        }
+        return Ok(());
     }
 
-    #[test]
-    fn find_gacha_url() {
-        let test_url = "https://gs.hoyoverse.com/genshin/event/e20190909gacha-v3/index.html?anythinghere&game_biz=hk4e_global";
-        let test_url_vec = test_url.as_bytes().to_vec();
-
-        let result = find_gacha_urls_in_slice(
-            &test_url_vec,
-            "gacha-v3",
-            "https://gs.hoyoverse.com/",
-            "game_biz=hk4e_global",
-        );
-        assert!(result.is_ok());
-        let result = result.unwrap();
-        assert_eq!(1, result.len());
-        assert_eq!(test_url, result[0]);
+    let result = if args.no_validate {
+        extractor
+            .extract_unverified_urls()
+            .map(|urls| urls.into_iter().next().expect("URLs are not empty"))
+    } else {
+        extractor.extract_url()
+    };
+    match result {
+        Ok(extracted) if Some(&extracted.url) != last_url.as_ref() => {
+            if last_url.is_some() {
+                println!();
+            }
+            match extracted.timestamp {
+                Some(timestamp) => {
+                    println!("New URL, last used at {}:", format_timestamp(timestamp))
+                }
+                None => println!("New URL:"),
+            }
+            println!("{}", extracted.url);
+            *last_url = Some(extracted.url);
+        }
+        Ok(_) => log::debug!("The URL has not changed"),
+        Err(error) => {
+            eprintln!("Failed to find gacha URL: {}", error);
+            if let Some(hint) = hint(&error) {
+                eprintln!("{}", hint);
+            }
+        }
     }
+    Ok(())
+}
 
-    // Verify that it can find multiple urls.
-    #[test]
-    fn find_gacha_urls_in_ascii() {
-        let test_url1 = "https://gs.hoyoverse.com/genshin/event/e20190909gacha-v3/index.html?ANYDATA11111&game_biz=hk4e_global";
-        let test_url2 = "https://gs.hoyoverse.com/genshin/event/e20190909gacha-v3/index.html?DIFFERTDATA22222&game_biz=hk4e_global";
-
-        let test_data: Vec<u8> = [
-            test_url1.as_bytes(),
-            // any data.
-            &[0xFF, 0x00, 0x3A, 0xBC],
-            test_url2.as_bytes(),
-        ]
-        .concat();
-
-        let result = find_gacha_urls_in_slice(
-            &test_data,
-            "gacha-v3",
-            "https://gs.hoyoverse.com/",
-            "game_biz=hk4e_global",
-        );
-        assert!(result.is_ok());
-        let result = result.unwrap();
-        assert_eq!(2, result.len());
-        assert_eq!(test_url1, result[0]);
-        assert_eq!(test_url2, result[1]);
+// Goes through the steps of finding a URL one by one, and prints what was found in each. Stops at
+// the first step that fails, with its error.
+fn doctor(
+    source: &SourceArgs,
+    no_validate: bool,
+    registry: GameRegistry,
+    jobs: u16,
+    definitions_path: Option<&Path>,
+) -> Result<()> {
+    println!("Game definitions: {} game types", registry.games.len());
+    match definitions_path {
+        Some(definitions_path) => {
+            println!("  Including the ones in {}", definitions_path.display())
+        }
+        None => println!("  Built-in only. Pass --games or set EXTRACT_WISH_URL_GAMES to add more"),
     }
 
-    // gacha-v3 marker is in the url but cannot find the end.
-    #[test]
-    fn no_gacha_url_has_marker_no_end_marker() {
-        let test_url =
-            "https://gs.hoyoverse.com/genshin/event/e20190909gacha-v3/index.html?anythinghere";
-        let test_url_vec = test_url.as_bytes().to_vec();
-        let result = find_gacha_urls_in_slice(
-            &test_url_vec,
-            "gacha-v3",
-            "https://gs.hoyoverse.com/",
-            "game_biz=hk4e_global",
-        );
-        assert!(result.is_err());
+    let installs = discover::find_default_installs(&registry);
+    println!("Installs found in the game logs: {}", installs.len());
+    for (i, install) in installs.iter().enumerate() {
+        println!("  [{}] {}", i + 1, describe_install(install));
     }
 
-    // gacha-v3 marker and game_biz=hk4e_global are present but cannot find https:// start.
-    #[test]
-    fn no_gacha_url_has_marker_has_end_marker_no_start_marker() {
-        let test_url = "verse.com/genshin/event/e20190909gacha-v3/index.html?anythinghere";
-        let test_url_vec = test_url.as_bytes().to_vec();
-        let result = find_gacha_urls_in_slice(
-            &test_url_vec,
-            "gacha-v3",
-            "https://gs.hoyoverse.com/",
-            "game_biz=hk4e_global",
-        );
-        assert!(result.is_err());
+    let extractor = open_extractor(source, registry, jobs)?;
+    let game_types = extractor.game_types();
+    println!(
+        "Searching for: {}",
+        game_types
+            .iter()
+            .map(|game_type| format!("{} ({})", game_type.name, game_type.region))
+            .join(", ")
+    );
+
+    if let Some(web_cache_dir) = extractor.web_cache_dir() {
+        if !web_cache_dir.is_dir() {
+            return Err(Error::WebCacheDirNotFound(web_cache_dir).into());
+        }
+        let cache_dirs = extract_wish_url::extractor::get_cache_dirs(&web_cache_dir)?;
+        println!("Cache directories, newest version first:");
+        for cache_dir in &cache_dirs {
+            let backend = if cache::simple::is_simple_cache(&cache_dir.path) {
+                "Simple Cache"
+            } else {
+                "blockfile"
+            };
+            println!("  {} ({})", cache_dir.path.display(), backend);
+        }
     }
 
-    #[test]
-    fn get_data2_path() -> Result<()> {
-        let dir = tempdir()?;
-        let cache_data_dir = dir
-            .path()
-            .join("GenshinImpact_Data")
-            .join("webCaches")
-            .join("4.5.6.7")
-            .join("Cache")
-            .join("Cache_Data");
-        std::fs::create_dir_all(&cache_data_dir)?;
-        std::fs::File::create(cache_data_dir.join("data_2"))?;
+    let urls = extractor.extract_unverified_urls()?;
+    println!(
+        "Gacha URLs in the cache: {}, the newest last used at {}",
+        urls.len(),
+        format_last_used(&urls[0])
+    );
+    if no_validate {
+        return Ok(());
+    }
 
-        assert!(
-            get_to_data2_file(&dir.path().join("GenshinImpact_Data").join("webCaches")).is_some()
-        );
-        Ok(())
+    let extracted = extractor.extract_url()?;
+    println!("Working URL: last used at {}", format_last_used(&extracted));
+    println!("{}", extracted.url);
+    Ok(())
+}
+
+fn write_json(path: &Path, value: &impl serde::Serialize) -> Result<()> {
+    let file =
+        fs::File::create(path).with_context(|| format!("Failed to create {}", path.display()))?;
+    serde_json::to_writer_pretty(file, value)
+        .with_context(|| format!("Failed to write {}", path.display()))
+}
+
+fn run(cli: Cli) -> Result<()> {
+    let registry = load_registry(&cli.global)?;
+    let jobs = cli.global.jobs;
+    match &cli.command.unwrap_or(Command::Extract(cli.extract)) {
+        Command::Extract(args) => extract(args, registry, jobs),
+        Command::Validate(args) => validate(args, registry, jobs),
+        Command::Scan(args) => scan(args, registry, jobs),
+        Command::Fetch { history, output } => fetch(history, output.as_deref(), registry, jobs),
+        Command::Export { history, formats } => export(history, formats, registry, jobs),
+        Command::Stats { history, output } => {
+            print_stats(history, output.as_deref(), registry, jobs)
+        }
+        Command::Watch(args) => watch(args, registry, jobs),
+        Command::Doctor {
+            source,
+            no_validate,
+        } => doctor(
+            source,
+            *no_validate,
+            registry,
+            jobs,
+            cli.global.games.as_deref(),
+        ),
     }
+}
 
-    #[test]
-    fn get_data2_path_multiple_versions() -> Result<()> {
-        let dir = tempdir()?;
-        let older_cache = dir
-            .path()
-            .join("GenshinImpact_Data")
-            .join("webCaches")
-            .join("1.2.3.5000")
-            .join("Cache")
-            .join("Cache_Data");
-        std::fs::create_dir_all(&older_cache)?;
-        std::fs::File::create(older_cache.join("data_2"))?;
-
-        let newer_cache = dir
-            .path()
-            .join("GenshinImpact_Data")
-            .join("webCaches")
-            // Although the right most number is smaller, this is newer.
-            .join("1.2.4.0")
-            .join("Cache")
-            .join("Cache_Data");
-        std::fs::create_dir_all(&newer_cache)?;
-        std::fs::File::create(newer_cache.join("data_2"))?;
-
-        let data_path =
-            get_to_data2_file(&dir.path().join("GenshinImpact_Data").join("webCaches")).unwrap();
-
-        assert_eq!(data_path, newer_cache.join("data_2"));
-        Ok(())
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+
+    let mut logger = env_logger::Builder::from_default_env();
+    if cli.global.verbose {
+        logger.filter_module("extract_wish_url", log::LevelFilter::Debug);
     }
+    logger.init();
+
+    match run(cli) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(error) => {
+            eprintln!("Error: {:#}", error);
+            let library_error = error.chain().find_map(|e| e.downcast_ref::<Error>());
+            if let Some(hint) = library_error.and_then(hint) {
+                eprintln!("{}", hint);
+            }
+            ExitCode::from(exit_code(&error))
+        }
+    }
+}
 
-    #[test]
-    fn test_zzz_url() -> Result<()> {
-        let mut server = mockito::Server::new();
-        let url= format!("{}{}{}",
-            "http://", 
-            &server.host_with_port(),
-            // Note that these include the required params.
-            "/index.html?extraparam=1234&authkey=key&authkey_ver=2&sign_type=sometype&game_biz=nap_global&lang=en&more=stuff&andsomemore=fluffs");
-
-        // Create a mock
-        let mock = server
-            // This path matches the above.
-            .mock("GET", "/index.html?extraparam=1234&authkey=key&authkey_ver=2&sign_type=sometype&game_biz=nap_global&lang=en&more=stuff&andsomemore=fluffs")
-            .with_status(200)
-            .with_header("content-type", "application/json")
-            // A minimal JSON to return retcode=0.
-            .with_body(r#"{"retcode": 0}"#)
-            .create();
-
-        let result = test_zzz_signal_url(&url)?;
-        // Verify that extraneous params are removed.
-        // Hardcoded 127.0.0.1 without a port number. Note that
-        // server.host_with_port() includes a port number.
-        // Its ok to change the host here if the framework changes.
-        assert_eq!("http://127.0.0.1/index.html?authkey=key&authkey_ver=2&sign_type=sometype&game_biz=nap_global&lang=en", result);
-        mock.assert();
-        Ok(())
+fn exit_code(error: &anyhow::Error) -> u8 {
+    if let Some(error) = error.downcast_ref::<CliError>() {
+        return match error {
+            CliError::NoInstall | CliError::NoSuchInstall(..) | CliError::PathNotFound(_) => {
+                EXIT_NO_INSTALL
+            }
+            CliError::UnknownGame(_) | CliError::NoUrlGiven => EXIT_USAGE,
+        };
+    }
+    if let Some(error) = error.chain().find_map(|e| e.downcast_ref::<Error>()) {
+        return library_exit_code(error);
     }
+    // E.g. fetching the gacha history failed.
+    if error.chain().any(|e| e.is::<reqwest::Error>()) {
+        return EXIT_RETRY_LATER;
+    }
+    EXIT_FAILURE
+}
 
-    #[test]
-    fn test_genshin_url() -> Result<()> {
-        let mut server = mockito::Server::new();
-        let url= format!("{}{}{}",
-            "http://", 
-            &server.host_with_port(),
-            "/index.html?extraparam=1234&authkey=key&authkey_ver=2&sign_type=sometype&game_biz=hk4e_global&lang=en&more=stuff&andsomemore=fluffs");
-
-        let mock = server
-            // Verify that the parameters are added.
-            .mock("GET", "/gacha_info/api/getGachaLog?andsomemore=fluffs&authkey=key&authkey_ver=2&extraparam=1234&gacha_type=301&game_biz=hk4e_global&lang=en-us&more=stuff&sign_type=sometype&size=5")
-            .with_status(200)
-            .with_header("content-type", "application/json")
-            // A minimal JSON to return retcode=0.
-            .with_body(r#"{"retcode": 0}"#)
-            .create();
-
-        let result = test_genshin_wish_url(&url, &server.host_with_port())?;
-
-        // Verify that the original URL is returned.
-        assert_eq!(
-            format!("http://{}/index.html?extraparam=1234&authkey=key&authkey_ver=2&sign_type=sometype&game_biz=hk4e_global&lang=en&more=stuff&andsomemore=fluffs",
-            &server.host_with_port()),
-            result);
-        mock.assert();
-        Ok(())
+fn library_exit_code(error: &Error) -> u8 {
+    match error {
+        Error::DataDirNotFound(_) => EXIT_NO_INSTALL,
+        Error::WebCacheDirNotFound(_)
+        | Error::NoVersionedCacheDir(_)
+        | Error::NoCacheData(_)
+        | Error::NoUrlsFound => EXIT_NO_URL,
+        // The newest URL is the most relevant one, like in Error::remedy().
+        Error::NoWorkingUrl(errors) => errors
+            .first()
+            .map_or(EXIT_URL_NOT_WORKING, library_exit_code),
+        Error::RateLimited { .. } | Error::Network(_) => EXIT_RETRY_LATER,
+        Error::AuthkeyExpired { .. }
+        | Error::AuthkeyInvalid { .. }
+        | Error::WrongGame { .. }
+        | Error::Api { .. }
+        | Error::InvalidUrl { .. }
+        | Error::InvalidResponse { .. } => EXIT_URL_NOT_WORKING,
     }
+}
+
+// Returns what the user can do about |error|, if anything.
+fn hint(error: &Error) -> Option<&'static str> {
+    Some(match error.remedy()? {
+        Remedy::CheckInstallPath => "Check that the path is the game install directory.",
+        Remedy::OpenGachaHistory => "Open the gacha history in game, then run this again.",
+        Remedy::RetryLater => "Wait a minute, then run this again.",
+        Remedy::CheckRegion => "The URL is for another game or region than expected.",
+        Remedy::CheckNetwork => "Check the network connection.",
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::CommandFactory;
 
     #[test]
-    fn test_zzz_url_retcode_not_0() {
-        let mut server = mockito::Server::new();
-        let url= format!("{}{}{}",
-            "http://", 
-            &server.host_with_port(),
-            // Note that these include the required params.
-            "/index.html?extraparam=1234&authkey=key&authkey_ver=2&sign_type=sometype&game_biz=nap_global&lang=en&more=stuff&andsomemore=fluffs");
-
-        // Create a mock
-        let mock = server
-            // This path matches the above.
-            .mock("GET", "/index.html?extraparam=1234&authkey=key&authkey_ver=2&sign_type=sometype&game_biz=nap_global&lang=en&more=stuff&andsomemore=fluffs")
-            .with_status(200)
-            .with_header("content-type", "application/json")
-            // Retcode is -1! The function should return an error.
-            .with_body(r#"{"retcode": -1}"#)
-            .create();
-
-        let result = test_zzz_signal_url(&url);
-        assert!(result.is_err());
-        mock.assert();
-    }
-
-    // TODO Might be good to move this to tests/ as integration tests. But this requires seprating
-    // this to library + executable first.
+    fn cli_is_valid() {
+        Cli::command().debug_assert();
+    }
+
+    // Without a subcommand, the arguments are the ones of extract, like before the subcommands.
     #[test]
-    fn test_genshin_pull_extractor_new() -> Result<()> {
-        let dir = tempdir()?;
-        let cache_data_dir = dir
-            .path()
-            .join("GenshinImpact_Data")
-            .join("webCaches")
-            .join("4.5.6.7")
-            .join("Cache")
-            .join("Cache_Data");
-        std::fs::create_dir_all(&cache_data_dir)?;
-        std::fs::File::create(cache_data_dir.join("data_2"))?;
-        PullExtractor::new(dir.path())?;
-        Ok(())
+    fn extract_is_default() {
+        let cli =
+            Cli::try_parse_from(["extract-wish-url", "/games/genshin", "--no-validate"]).unwrap();
+        assert!(cli.command.is_none());
+        assert_eq!(
+            Some(PathBuf::from("/games/genshin")),
+            cli.extract.source.install_path
+        );
+        assert!(cli.extract.no_validate);
+
+        assert!(Cli::try_parse_from(["extract-wish-url", "export", "/games/genshin"]).is_err());
     }
 
     #[test]
-    fn test_zzz_pull_extractor_new() -> Result<()> {
-        let dir = tempdir()?;
-        let cache_data_dir = dir
-            .path()
-            .join("ZenlessZoneZero_Data")
-            .join("webCaches")
-            .join("4.5.6.7")
-            .join("Cache")
-            .join("Cache_Data");
-        std::fs::create_dir_all(&cache_data_dir)?;
-        std::fs::File::create(cache_data_dir.join("data_2"))?;
-        PullExtractor::new(dir.path())?;
-        Ok(())
+    fn choose_install() {
+        let cli = Cli::try_parse_from(["extract-wish-url", "--install", "2"]).unwrap();
+        assert_eq!(Some(2), cli.extract.source.install);
+        assert!(Cli::try_parse_from(["extract-wish-url", "--install", "0"]).is_err());
+        assert!(
+            Cli::try_parse_from(["extract-wish-url", "/games/genshin", "--install", "2"]).is_err()
+        );
+        assert!(
+            Cli::try_parse_from(["extract-wish-url", "--all-installs", "--install", "2"]).is_err()
+        );
+        assert!(
+            Cli::try_parse_from(["extract-wish-url", "extract", "--all-installs", "--json"])
+                .is_ok()
+        );
     }
 
     #[test]
-    fn test_zzz_pull_extractor_extract() -> Result<()> {
-        let dir = tempdir()?;
-        let cache_data_dir = dir
-            .path()
-            .join("ZenlessZoneZero_Data")
-            .join("webCaches")
-            .join("4.5.6.7")
-            .join("Cache")
-            .join("Cache_Data");
-        std::fs::create_dir_all(&cache_data_dir)?;
-        let data_2_file = std::fs::File::create(cache_data_dir.join("data_2"))?;
-        let extractor = PullExtractor::new(dir.path())?;
-
-        let mut server = mockito::Server::new();
-        let url= format!("{}{}{}",
-            "https://", 
-            &server.host_with_port(),
-            // Note that these include the required params.
-            "/getGachaLog/index.html?lang=en&extraparam=1234&authkey=key&authkey_ver=2&sign_type=sometype&game_biz=nap_global");
-
-        let mut writer = BufWriter::new(data_2_file);
-        writer.write_all(url.as_bytes())?;
-        writer.flush()?;
-
-        // Create a mock
-        let mock = server
-            // This path matches the above.
-            .mock("GET", "/getGachaLog/index.html?lang=en&extraparam=1234&authkey=key&authkey_ver=2&sign_type=sometype&game_biz=nap_global")
-            .with_status(200)
-            .with_header("content-type", "application/json")
-            // Retcode is -1! The function should return an error.
-            .with_body(r#"{"retcode": 0}"#)
-            .create();
-
-        let result = extractor.extract_url()?;
-        assert_eq!("https://127.0.0.1/getGachaLog/index.html?lang=en&authkey=key&authkey_ver=2&sign_type=sometype&game_biz=nap_global", result);
-        mock.assert();
-
-        Ok(())
+    fn exit_codes() {
+        let code = |error: Error| exit_code(&anyhow::Error::from(error).context("context"));
+        assert_eq!(
+            EXIT_NO_INSTALL,
+            code(Error::DataDirNotFound(PathBuf::new()))
+        );
+        assert_eq!(EXIT_NO_URL, code(Error::NoUrlsFound));
+        assert_eq!(
+            EXIT_URL_NOT_WORKING,
+            code(Error::NoWorkingUrl(vec![Error::AuthkeyExpired {
+                message: String::new()
+            }]))
+        );
+        assert_eq!(
+            EXIT_RETRY_LATER,
+            code(Error::NoWorkingUrl(vec![Error::RateLimited {
+                message: String::new()
+            }]))
+        );
+        assert_eq!(
+            EXIT_USAGE,
+            exit_code(&CliError::UnknownGame("game".to_string()).into())
+        );
     }
 }
@@ -0,0 +1,258 @@
+// A machine-readable report of an extraction, so that scripts and bots do not have to parse the
+// text output.
+
+use crate::error::{Error, Result};
+use crate::extractor::{CheckedUrl, ExtractedUrl, PullExtractor, Source};
+use crate::games::{GameTypeData, Region};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::path::PathBuf;
+
+const DATA2_FILE_NAME: &str = "data_2";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CandidateStatus {
+    Works,
+    Failed,
+    // The URL was not checked, e.g. without network.
+    Unverified,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct CandidateReport {
+    pub url: String,
+    pub game: String,
+    pub region: Region,
+    // When the URL was last used, in RFC 3339. None if not known.
+    pub last_used: Option<String>,
+    // The versioned directory in webCaches that the URL was found in.
+    pub cache_version_dir: PathBuf,
+    pub status: CandidateStatus,
+    // The retcode of the API, if it rejected the URL.
+    pub retcode: Option<i64>,
+    // Why the URL failed the validation.
+    pub error: Option<String>,
+}
+
+impl CandidateReport {
+    fn new(extracted: &ExtractedUrl, status: CandidateStatus, error: Option<&Error>) -> Self {
+        Self {
+            url: extracted.url.clone(),
+            game: extracted.game_type.name.clone(),
+            region: extracted.game_type.region,
+            last_used: extracted
+                .timestamp
+                .map(|timestamp| DateTime::<Utc>::from(timestamp).to_rfc3339()),
+            cache_version_dir: extracted.source_dir.clone(),
+            status,
+            retcode: error.and_then(Error::retcode),
+            error: error.map(ToString::to_string),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct ExtractionReport {
+    // The game and the region of the chosen URL. Before a URL is found, the game of the data
+    // directory, if only one game was searched for.
+    pub game: Option<String>,
+    pub region: Option<Region>,
+    pub install_path: Option<PathBuf>,
+    // The data_2 file and the versioned directory in webCaches that the chosen URL was found in.
+    pub data2_path: Option<PathBuf>,
+    pub cache_version_dir: Option<PathBuf>,
+    // Best first.
+    pub candidates: Vec<CandidateReport>,
+    // The URL to use. None if none of the URLs works.
+    pub url: Option<String>,
+    // Why no URL was chosen.
+    pub error: Option<String>,
+}
+
+impl ExtractionReport {
+    // Starts a report on what |extractor| searches.
+    pub fn new(extractor: &PullExtractor) -> Self {
+        let mut report = Self::default();
+        match extractor.source() {
+            Source::InstallDir(install_path) => report.install_path = Some(install_path.clone()),
+            Source::Data2File(data2_path) => report.data2_path = Some(data2_path.clone()),
+        }
+        let game_types = extractor.game_types();
+        if game_types
+            .iter()
+            .all(|game_type| game_type.name == game_types[0].name)
+        {
+            report.game = Some(game_types[0].name.clone());
+        }
+        if let [game_type] = game_types {
+            report.region = Some(game_type.region);
+        }
+        report
+    }
+
+    // Adds the results of PullExtractor::check_all_urls(), and chooses the first URL that works,
+    // if any.
+    pub fn add_checked_urls(&mut self, checked: &[CheckedUrl]) {
+        self.candidates
+            .extend(checked.iter().map(|checked| match &checked.error {
+                None => CandidateReport::new(&checked.extracted, CandidateStatus::Works, None),
+                Some(error) => {
+                    CandidateReport::new(&checked.extracted, CandidateStatus::Failed, Some(error))
+                }
+            }));
+        if let Some(working) = checked.iter().find(|checked| checked.extracted.verified) {
+            self.choose(&working.extracted);
+        }
+    }
+
+    // Adds the results of PullExtractor::extract_unverified_urls(), and chooses the first one.
+    pub fn add_unverified_urls(&mut self, urls: &[ExtractedUrl]) {
+        self.candidates.extend(
+            urls.iter()
+                .map(|url| CandidateReport::new(url, CandidateStatus::Unverified, None)),
+        );
+        if let Some(best) = urls.first() {
+            self.choose(best);
+        }
+    }
+
+    pub fn set_error(&mut self, error: &anyhow::Error) {
+        self.error = Some(format!("{:#}", error));
+    }
+
+    fn choose(&mut self, extracted: &ExtractedUrl) {
+        self.game = Some(extracted.game_type.name.clone());
+        self.region = Some(extracted.game_type.region);
+        self.cache_version_dir = Some(extracted.source_dir.clone());
+        let data2_path = extracted.cache_dir.join(DATA2_FILE_NAME);
+        if self.data2_path.is_none() && data2_path.is_file() {
+            self.data2_path = Some(data2_path);
+        }
+        self.url = Some(extracted.url.clone());
+    }
+}
+
+// The result of checking a URL that was not found in a cache, e.g. one that was pasted.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ValidationReport {
+    // The URL that validate() returned if the URL works, otherwise the URL as given.
+    pub url: String,
+    // None if the game of the URL is not known.
+    pub game: Option<String>,
+    pub region: Option<Region>,
+    pub status: CandidateStatus,
+    pub retcode: Option<i64>,
+    pub error: Option<String>,
+}
+
+impl ValidationReport {
+    // |result| is the result of validating |url| for |game_type|.
+    pub fn new(url: &str, game_type: Option<&GameTypeData>, result: &Result<String>) -> Self {
+        let (url, status, error) = match result {
+            Ok(validated) => (validated.as_str(), CandidateStatus::Works, None),
+            Err(error) => (url, CandidateStatus::Failed, Some(error)),
+        };
+        Self {
+            url: url.to_string(),
+            game: game_type.map(|game_type| game_type.name.clone()),
+            region: game_type.map(|game_type| game_type.region),
+            status,
+            retcode: error.and_then(Error::retcode),
+            error: error.map(ToString::to_string),
+        }
+    }
+
+    // |url| was not checked, e.g. without network.
+    pub fn unverified(url: &str, game_type: Option<&GameTypeData>) -> Self {
+        Self {
+            url: url.to_string(),
+            game: game_type.map(|game_type| game_type.name.clone()),
+            region: game_type.map(|game_type| game_type.region),
+            status: CandidateStatus::Unverified,
+            retcode: None,
+            error: None,
+        }
+    }
+}
+
+// A URL found by scanning a file, e.g. a HAR export or a memory dump.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ScanReport {
+    // The file that the URL was found in. "-" for stdin.
+    pub source: PathBuf,
+    #[serde(flatten)]
+    pub validation: ValidationReport,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::games::GameRegistry;
+    use std::time::{Duration, SystemTime};
+
+    fn extracted(url: &str, verified: bool) -> ExtractedUrl {
+        let game_type = GameRegistry::builtin().select("hkrpg_cn").games.remove(0);
+        ExtractedUrl {
+            url: url.to_string(),
+            timestamp: Some(SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000)),
+            source_dir: PathBuf::from("webCaches/2.5.0.0"),
+            cache_dir: PathBuf::from("webCaches/2.5.0.0/Cache/Cache_Data"),
+            game_type,
+            verified,
+        }
+    }
+
+    #[test]
+    fn first_working_url_is_chosen() {
+        let mut report = ExtractionReport::default();
+        report.add_checked_urls(&[
+            CheckedUrl {
+                extracted: extracted("https://a.com/?authkey=expired", false),
+                error: Some(Error::AuthkeyExpired {
+                    message: "authkey timeout".to_string(),
+                }),
+            },
+            CheckedUrl {
+                extracted: extracted("https://a.com/?authkey=working", true),
+                error: None,
+            },
+        ]);
+
+        assert_eq!(
+            Some("https://a.com/?authkey=working".to_string()),
+            report.url
+        );
+        assert_eq!(Some("Honkai: Star Rail".to_string()), report.game);
+        assert_eq!(Some(Region::China), report.region);
+        assert_eq!(
+            Some(PathBuf::from("webCaches/2.5.0.0")),
+            report.cache_version_dir
+        );
+        assert_eq!(None, report.error);
+        assert_eq!(CandidateStatus::Failed, report.candidates[0].status);
+        assert_eq!(Some(-101), report.candidates[0].retcode);
+        assert_eq!(
+            Some("2023-11-14T22:13:20+00:00".to_string()),
+            report.candidates[0].last_used
+        );
+        assert_eq!(CandidateStatus::Works, report.candidates[1].status);
+        assert_eq!(None, report.candidates[1].retcode);
+    }
+
+    #[test]
+    fn no_working_url() {
+        let mut report = ExtractionReport::default();
+        report.add_checked_urls(&[CheckedUrl {
+            extracted: extracted("https://a.com/?authkey=broken", false),
+            error: Some(Error::Api {
+                retcode: -1,
+                message: "system error".to_string(),
+            }),
+        }]);
+
+        assert_eq!(None, report.url);
+        assert_eq!(CandidateStatus::Failed, report.candidates[0].status);
+        assert_eq!(Some(-1), report.candidates[0].retcode);
+    }
+}
@@ -0,0 +1,383 @@
+// Pity and 50/50 statistics of the gacha history.
+
+use crate::games::GameTypeData;
+use crate::history::{BannerHistory, GachaHistory};
+use serde::Serialize;
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FiftyFiftyResult {
+    Won,
+    Lost,
+    // The previous 50/50 was lost, so this one was a featured item for sure.
+    Guaranteed,
+}
+
+impl fmt::Display for FiftyFiftyResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FiftyFiftyResult::Won => write!(f, "won"),
+            FiftyFiftyResult::Lost => write!(f, "lost"),
+            FiftyFiftyResult::Guaranteed => write!(f, "guaranteed"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct HighestRankPull {
+    pub name: String,
+    pub time: String,
+    // The number of pulls it took, including this one.
+    pub pity: u32,
+    // None for banners without a 50/50.
+    pub fifty_fifty: Option<FiftyFiftyResult>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Streak {
+    pub result: FiftyFiftyResult,
+    pub count: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct FiftyFiftyStats {
+    pub won: u32,
+    pub lost: u32,
+    // The current run of the same 50/50 result. Guaranteed pulls do not break or extend it.
+    pub streak: Option<Streak>,
+    // Whether the next highest rank item is guaranteed to be featured.
+    pub guaranteed: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct BannerStats {
+    pub gacha_type: String,
+    pub name: String,
+    pub total_pulls: usize,
+    // Pulls since the last highest rank item, e.g. 5 star.
+    pub highest_rank_pity: u32,
+    // Pulls since the last item of the second highest rank or above, e.g. 4 star.
+    pub second_rank_pity: u32,
+    // Oldest first.
+    pub highest_rank_pulls: Vec<HighestRankPull>,
+    pub highest_rank_average_pity: Option<f64>,
+    pub second_rank_count: u32,
+    pub second_rank_average_pity: Option<f64>,
+    // None for banners without a 50/50.
+    pub fifty_fifty: Option<FiftyFiftyStats>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct HistoryStats {
+    pub game: String,
+    pub uid: String,
+    pub banners: Vec<BannerStats>,
+    // Names of the highest and the second highest ranks, for the report.
+    #[serde(skip)]
+    rank_names: (&'static str, &'static str),
+}
+
+impl HistoryStats {
+    pub fn new(history: &GachaHistory, game_type: &GameTypeData) -> Self {
+        let banners = history
+            .banners
+            .iter()
+            .map(|banner| {
+                let fifty_fifty = game_type
+                    .banners
+                    .iter()
+                    .any(|b| b.gacha_type == banner.gacha_type && b.fifty_fifty);
+                banner_stats(banner, game_type, fifty_fifty)
+            })
+            .collect();
+        Self {
+            game: history.game.clone(),
+            uid: history.uid.clone(),
+            banners,
+            rank_names: if game_type.highest_rank_type == 4 {
+                ("S", "A")
+            } else {
+                ("5★", "4★")
+            },
+        }
+    }
+}
+
+fn average(sum: u32, count: u32) -> Option<f64> {
+    (count > 0).then(|| sum as f64 / count as f64)
+}
+
+fn banner_stats(
+    banner: &BannerHistory,
+    game_type: &GameTypeData,
+    fifty_fifty: bool,
+) -> BannerStats {
+    let highest_rank = game_type.highest_rank_type;
+    let mut highest_rank_pity = 0;
+    let mut second_rank_pity = 0;
+    let mut highest_rank_pulls = vec![];
+    let mut second_rank_count = 0;
+    let mut second_rank_pity_sum = 0;
+    let mut guaranteed = false;
+
+    // The records are newest first.
+    for record in banner.records.iter().rev() {
+        highest_rank_pity += 1;
+        second_rank_pity += 1;
+        let rank = record.rank_type.parse::<u32>().unwrap_or_default();
+
+        if rank >= highest_rank {
+            let result = fifty_fifty.then(|| {
+                if guaranteed {
+                    guaranteed = false;
+                    FiftyFiftyResult::Guaranteed
+                } else if game_type.is_standard_item(&record.name, &record.time) {
+                    guaranteed = true;
+                    FiftyFiftyResult::Lost
+                } else {
+                    FiftyFiftyResult::Won
+                }
+            });
+            highest_rank_pulls.push(HighestRankPull {
+                name: record.name.clone(),
+                time: record.time.clone(),
+                pity: highest_rank_pity,
+                fifty_fifty: result,
+            });
+            highest_rank_pity = 0;
+            second_rank_pity = 0;
+        } else if rank + 1 == highest_rank {
+            second_rank_count += 1;
+            second_rank_pity_sum += second_rank_pity;
+            second_rank_pity = 0;
+        }
+    }
+
+    let fifty_fifty = fifty_fifty.then(|| {
+        let results = highest_rank_pulls
+            .iter()
+            .filter_map(|pull| pull.fifty_fifty)
+            .filter(|result| *result != FiftyFiftyResult::Guaranteed)
+            .collect::<Vec<_>>();
+        let streak = results.last().map(|last| Streak {
+            result: *last,
+            count: results.iter().rev().take_while(|r| *r == last).count() as u32,
+        });
+        FiftyFiftyStats {
+            won: results
+                .iter()
+                .filter(|r| **r == FiftyFiftyResult::Won)
+                .count() as u32,
+            lost: results
+                .iter()
+                .filter(|r| **r == FiftyFiftyResult::Lost)
+                .count() as u32,
+            streak,
+            guaranteed,
+        }
+    });
+
+    let highest_rank_pity_sum = highest_rank_pulls.iter().map(|pull| pull.pity).sum();
+    BannerStats {
+        gacha_type: banner.gacha_type.clone(),
+        name: banner.name.clone(),
+        total_pulls: banner.records.len(),
+        highest_rank_pity,
+        second_rank_pity,
+        highest_rank_average_pity: average(highest_rank_pity_sum, highest_rank_pulls.len() as u32),
+        highest_rank_pulls,
+        second_rank_count,
+        second_rank_average_pity: average(second_rank_pity_sum, second_rank_count),
+        fifty_fifty,
+    }
+}
+
+fn format_average(average: Option<f64>) -> String {
+    average.map_or_else(|| "-".to_string(), |average| format!("{:.1}", average))
+}
+
+impl fmt::Display for HistoryStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (highest, second) = self.rank_names;
+        writeln!(f, "{} (UID {})", self.game, self.uid)?;
+        for banner in &self.banners {
+            writeln!(f)?;
+            writeln!(f, "{}: {} pulls", banner.name, banner.total_pulls)?;
+            writeln!(
+                f,
+                "  Pity: {} {}, {} {}",
+                banner.highest_rank_pity, highest, banner.second_rank_pity, second
+            )?;
+            writeln!(
+                f,
+                "  {}: {}, average pity {}",
+                highest,
+                banner.highest_rank_pulls.len(),
+                format_average(banner.highest_rank_average_pity)
+            )?;
+            writeln!(
+                f,
+                "  {}: {}, average pity {}",
+                second,
+                banner.second_rank_count,
+                format_average(banner.second_rank_average_pity)
+            )?;
+            if let Some(fifty_fifty) = &banner.fifty_fifty {
+                write!(
+                    f,
+                    "  50/50: {} won, {} lost",
+                    fifty_fifty.won, fifty_fifty.lost
+                )?;
+                if let Some(streak) = &fifty_fifty.streak {
+                    write!(f, ", streak: {} {}", streak.count, streak.result)?;
+                }
+                writeln!(
+                    f,
+                    ", next is {}",
+                    if fifty_fifty.guaranteed {
+                        "guaranteed"
+                    } else {
+                        "a 50/50"
+                    }
+                )?;
+            }
+            for pull in &banner.highest_rank_pulls {
+                write!(f, "  {} {} ({})", pull.time, pull.name, pull.pity)?;
+                if let Some(result) = pull.fifty_fifty {
+                    write!(f, " {}", result)?;
+                }
+                writeln!(f)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::games::GameRegistry;
+    use crate::history::test_util::{self, record};
+    use crate::history::GachaRecord;
+
+    // Builds a banner from (name, rank) pairs, oldest first.
+    fn banner(gacha_type: &str, pulls: &[(&str, &str)]) -> BannerHistory {
+        let records = pulls
+            .iter()
+            .enumerate()
+            .rev()
+            .map(|(i, (name, rank))| GachaRecord {
+                name: name.to_string(),
+                item_type: "Character".to_string(),
+                rank_type: rank.to_string(),
+                ..record(&(i + 1).to_string(), gacha_type)
+            })
+            .collect();
+        test_util::banner(gacha_type, records)
+    }
+
+    fn genshin() -> GameTypeData {
+        GameRegistry::builtin()
+            .games
+            .into_iter()
+            .find(|game| game.data_dir_name == "GenshinImpact_Data")
+            .unwrap()
+    }
+
+    fn history(banners: Vec<BannerHistory>) -> GachaHistory {
+        test_util::history("hk4e_global", banners)
+    }
+
+    #[test]
+    fn character_event_banner() {
+        let pulls = [
+            ("Sword", "3"),
+            ("Fischl", "4"),
+            ("Diluc", "5"),
+            ("Sword", "3"),
+            ("Hu Tao", "5"),
+            ("Sword", "3"),
+            ("Bennett", "4"),
+            ("Raiden Shogun", "5"),
+            ("Sword", "3"),
+            ("Sword", "3"),
+        ];
+        let stats = HistoryStats::new(&history(vec![banner("301", &pulls)]), &genshin());
+        let banner = &stats.banners[0];
+
+        assert_eq!(10, banner.total_pulls);
+        assert_eq!(2, banner.highest_rank_pity);
+        assert_eq!(2, banner.second_rank_pity);
+        assert_eq!(
+            vec![
+                (3, Some(FiftyFiftyResult::Lost)),
+                (2, Some(FiftyFiftyResult::Guaranteed)),
+                (3, Some(FiftyFiftyResult::Won)),
+            ],
+            banner
+                .highest_rank_pulls
+                .iter()
+                .map(|pull| (pull.pity, pull.fifty_fifty))
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(Some(8.0 / 3.0), banner.highest_rank_average_pity);
+        assert_eq!(2, banner.second_rank_count);
+        // Fischl after 2 pulls, Bennett after 2 pulls since Hu Tao.
+        assert_eq!(Some(2.0), banner.second_rank_average_pity);
+        assert_eq!(
+            Some(FiftyFiftyStats {
+                won: 1,
+                lost: 1,
+                streak: Some(Streak {
+                    result: FiftyFiftyResult::Won,
+                    count: 1
+                }),
+                guaranteed: false,
+            }),
+            banner.fifty_fifty
+        );
+    }
+
+    #[test]
+    fn lost_fifty_fifty_is_guaranteed() {
+        let pulls = [("Keqing", "5"), ("Sword", "3")];
+        let stats = HistoryStats::new(&history(vec![banner("301", &pulls)]), &genshin());
+        let fifty_fifty = stats.banners[0].fifty_fifty.as_ref().unwrap();
+        assert!(fifty_fifty.guaranteed);
+        assert_eq!(1, fifty_fifty.lost);
+    }
+
+    // An item that joined the standard pool wins the 50/50 on its own banner, before that.
+    #[test]
+    fn item_joins_standard_pool() {
+        let pull = |time: &str| GachaRecord {
+            time: time.to_string(),
+            ..banner("301", &[("Tighnari", "5")]).records.remove(0)
+        };
+        let banner = test_util::banner(
+            "301",
+            vec![pull("2022-10-01 12:00:00"), pull("2022-08-25 12:00:00")],
+        );
+        let stats = HistoryStats::new(&history(vec![banner]), &genshin());
+        assert_eq!(
+            vec![Some(FiftyFiftyResult::Won), Some(FiftyFiftyResult::Lost)],
+            stats.banners[0]
+                .highest_rank_pulls
+                .iter()
+                .map(|pull| pull.fifty_fifty)
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn standard_banner_has_no_fifty_fifty() {
+        let pulls = [("Sword", "3"), ("Diluc", "5")];
+        let stats = HistoryStats::new(&history(vec![banner("200", &pulls)]), &genshin());
+        let banner = &stats.banners[0];
+        assert!(banner.fifty_fifty.is_none());
+        assert_eq!(None, banner.highest_rank_pulls[0].fifty_fifty);
+        assert_eq!(None, banner.second_rank_average_pity);
+        assert!(stats.to_string().contains("Banner 200: 2 pulls"));
+    }
+}
@@ -0,0 +1,172 @@
+// A local store of the gacha history, so that the history outlives the API's retention period
+// (about six months). The store is a JSON file with the history of each account.
+
+use crate::history::{compare_ids, GachaHistory, GachaRecord};
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fs;
+use std::io::{BufWriter, ErrorKind, Write};
+use std::path::Path;
+
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HistoryStore {
+    // One entry per account, i.e. per game_biz and uid.
+    pub accounts: Vec<GachaHistory>,
+}
+
+impl HistoryStore {
+    // Loads the store at |path|. A missing file is an empty store.
+    pub fn load(path: &Path) -> Result<Self> {
+        let json = match fs::read_to_string(path) {
+            Ok(json) => json,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Self::default()),
+            Err(e) => return Err(e).with_context(|| format!("Failed to read {}", path.display())),
+        };
+        serde_json::from_str(&json).with_context(|| format!("Failed to parse {}", path.display()))
+    }
+
+    // Saves the store to |path|. The file is replaced at once, so that an interrupted save does
+    // not lose the stored history.
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let dir = match path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        };
+        let file = tempfile::NamedTempFile::new_in(dir)
+            .with_context(|| format!("Failed to create a temporary file in {}", dir.display()))?;
+        let mut writer = BufWriter::new(file.as_file());
+        serde_json::to_writer_pretty(&mut writer, self)
+            .context("Failed to write the history store")?;
+        writer.flush()?;
+        drop(writer);
+        file.persist(path)
+            .with_context(|| format!("Failed to save {}", path.display()))?;
+        Ok(())
+    }
+
+    pub fn account(&self, game_biz: &str, uid: &str) -> Option<&GachaHistory> {
+        self.accounts
+            .iter()
+            .find(|account| account.game_biz == game_biz && account.uid == uid)
+    }
+
+    // Returns the (uid, id) pairs of all the stored records of |game_biz|.
+    pub fn known_ids(&self, game_biz: &str) -> HashSet<(String, String)> {
+        self.accounts
+            .iter()
+            .filter(|account| account.game_biz == game_biz)
+            .flat_map(|account| account.banners.iter())
+            .flat_map(|banner| banner.records.iter())
+            .map(|record| (record.uid.clone(), record.id.clone()))
+            .collect()
+    }
+
+    // Merges |history| into the store, and returns the number of records that were added.
+    // Records that are already stored are skipped.
+    pub fn merge(&mut self, history: GachaHistory) -> usize {
+        if history.uid.is_empty() {
+            return 0;
+        }
+        let Some(account) = self
+            .accounts
+            .iter_mut()
+            .find(|account| account.game_biz == history.game_biz && account.uid == history.uid)
+        else {
+            let added = history.record_count();
+            self.accounts.push(history);
+            return added;
+        };
+
+        account.region_time_zone = history.region_time_zone.or(account.region_time_zone);
+        let mut added = 0;
+        for banner in history.banners {
+            let Some(stored) = account
+                .banners
+                .iter_mut()
+                .find(|stored| stored.gacha_type == banner.gacha_type)
+            else {
+                added += banner.records.len();
+                account.banners.push(banner);
+                continue;
+            };
+
+            let stored_ids = stored
+                .records
+                .iter()
+                .map(|record| record.id.clone())
+                .collect::<HashSet<_>>();
+            let new_records = banner
+                .records
+                .into_iter()
+                .filter(|record| !stored_ids.contains(&record.id))
+                .collect::<Vec<GachaRecord>>();
+            added += new_records.len();
+            stored.records.extend(new_records);
+            // Keep the records newest first, like the API.
+            stored.records.sort_by(|a, b| compare_ids(&b.id, &a.id));
+        }
+        added
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::history::test_util::{self, banner, record, UID};
+    use tempfile::tempdir;
+
+    fn history(ids: &[&str]) -> GachaHistory {
+        test_util::history(
+            "hk4e_global",
+            vec![banner(
+                "301",
+                ids.iter().map(|id| record(id, "301")).collect(),
+            )],
+        )
+    }
+
+    #[test]
+    fn merge_adds_only_new_records() {
+        let mut store = HistoryStore::default();
+        assert_eq!(2, store.merge(history(&["2", "1"])));
+        assert_eq!(1, store.merge(history(&["3", "2"])));
+        assert_eq!(0, store.merge(history(&["3"])));
+
+        let account = store.account("hk4e_global", UID).unwrap();
+        assert_eq!(
+            vec!["3", "2", "1"],
+            account.banners[0]
+                .records
+                .iter()
+                .map(|record| record.id.as_str())
+                .collect::<Vec<_>>()
+        );
+        assert!(store
+            .known_ids("hk4e_global")
+            .contains(&(UID.to_string(), "1".to_string())));
+        assert!(store.known_ids("nap_global").is_empty());
+    }
+
+    #[test]
+    fn merge_without_uid_is_ignored() {
+        let mut store = HistoryStore::default();
+        let mut history = history(&[]);
+        history.uid.clear();
+        assert_eq!(0, store.merge(history));
+        assert!(store.accounts.is_empty());
+    }
+
+    #[test]
+    fn save_and_load() -> Result<()> {
+        let dir = tempdir()?;
+        let path = dir.path().join("store.json");
+        assert_eq!(HistoryStore::default(), HistoryStore::load(&path)?);
+
+        let mut store = HistoryStore::default();
+        store.merge(history(&["2", "1"]));
+        store.save(&path)?;
+        assert_eq!(store, HistoryStore::load(&path)?);
+        Ok(())
+    }
+}
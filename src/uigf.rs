@@ -0,0 +1,410 @@
+// Converts the fetched gacha history to UIGF v4, the interchange format that most trackers
+// import. See https://uigf.org/en/standards/uigf.html.
+
+use crate::history::{compare_ids, GachaHistory, GachaRecord};
+use anyhow::{bail, Context, Result};
+use itertools::Itertools;
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+const UIGF_VERSION: &str = "v4.0";
+const EXPORT_APP: &str = env!("CARGO_PKG_NAME");
+const EXPORT_APP_VERSION: &str = env!("CARGO_PKG_VERSION");
+// Used when the API did not return the time zone, and the UID does not tell it. This is the time
+// zone of the CN, Asia and TW/HK/MO servers.
+const DEFAULT_TIMEZONE: i32 = 8;
+
+// Item names to item ids. Genshin's API returns an empty item_id, which UIGF requires, so the ids
+// are resolved from the names with this.
+pub type ItemIds = HashMap<String, String>;
+
+// Loads the item ids from |path|, a JSON object from the names to the ids, e.g. the UIGF
+// dictionary of the language of the history. The ids can be strings or numbers.
+pub fn load_item_ids(path: &Path) -> Result<ItemIds> {
+    let json = fs::read_to_string(path)
+        .with_context(|| format!("Failed to read item ids {}", path.display()))?;
+    let ids: HashMap<String, Value> = serde_json::from_str(&json)
+        .with_context(|| format!("Failed to parse item ids {}", path.display()))?;
+    ids.into_iter()
+        .map(|(name, id)| match id {
+            Value::String(id) => Ok((name, id)),
+            Value::Number(id) => Ok((name, id.to_string())),
+            other => bail!(
+                "The item id of {} is not a string or a number: {}",
+                name,
+                other
+            ),
+        })
+        .collect()
+}
+
+#[derive(Debug, Serialize)]
+pub struct Uigf {
+    info: Info,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    hk4e: Vec<Account<Hk4eItem>>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    hkrpg: Vec<Account<GachaItem>>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    nap: Vec<Account<GachaItem>>,
+}
+
+#[derive(Debug, Serialize)]
+struct Info {
+    export_timestamp: i64,
+    export_app: &'static str,
+    export_app_version: &'static str,
+    version: &'static str,
+}
+
+#[derive(Debug, Serialize)]
+struct Account<T> {
+    uid: String,
+    timezone: i32,
+    lang: String,
+    list: Vec<T>,
+}
+
+// Genshin records carry the banner that they belong to, since the second character event banner
+// (400) shares its history with the first one (301).
+#[derive(Debug, Serialize)]
+struct Hk4eItem {
+    uigf_gacha_type: String,
+    gacha_type: String,
+    // Resolved from the name with ItemIds, since Genshin's API returns an empty one.
+    item_id: String,
+    count: String,
+    time: String,
+    name: String,
+    item_type: String,
+    rank_type: String,
+    id: String,
+}
+
+// Star Rail and ZZZ records.
+#[derive(Debug, Serialize)]
+struct GachaItem {
+    gacha_id: String,
+    gacha_type: String,
+    item_id: String,
+    count: String,
+    time: String,
+    name: String,
+    item_type: String,
+    rank_type: String,
+    id: String,
+}
+
+fn uigf_gacha_type(gacha_type: &str) -> &str {
+    match gacha_type {
+        "400" => "301",
+        other => other,
+    }
+}
+
+fn count(record: &GachaRecord) -> String {
+    if record.count.is_empty() {
+        "1".to_string()
+    } else {
+        record.count.clone()
+    }
+}
+
+// Returns the records of all banners, oldest first.
+fn records_oldest_first(history: &GachaHistory) -> Vec<&GachaRecord> {
+    let mut records = history
+        .banners
+        .iter()
+        .flat_map(|banner| banner.records.iter())
+        .collect::<Vec<_>>();
+    records.sort_by(|a, b| compare_ids(&a.id, &b.id));
+    records
+}
+
+// Returns the time zone of the server of |uid|, by the digit before the last 8 digits, like the
+// UIGF convention. Genshin and Star Rail share the digits, e.g. 6 for America (UTC-5) and 7 for
+// Europe (UTC+1). Genshin's API does not return the time zone, so this is the only way to tell it.
+fn uid_timezone(game: &str, uid: &str) -> Option<i32> {
+    if !matches!(game, "hk4e" | "hkrpg") || uid.len() < 9 {
+        return None;
+    }
+    match uid.as_bytes()[uid.len() - 9] {
+        b'6' => Some(-5),
+        b'7' => Some(1),
+        b'1'..=b'5' | b'8' | b'9' => Some(8),
+        _ => None,
+    }
+}
+
+// Returns the item_id of |record|, resolving it from the name with |item_ids| if the API did not
+// return it.
+fn item_id<'a>(record: &'a GachaRecord, item_ids: &'a ItemIds) -> Option<&'a str> {
+    if !record.item_id.is_empty() {
+        return Some(&record.item_id);
+    }
+    item_ids.get(&record.name).map(String::as_str)
+}
+
+impl Uigf {
+    // Converts |history| to UIGF. |export_timestamp| is in seconds since the Unix epoch.
+    // |item_ids| resolves the item ids that the API did not return. Fails if a record has no
+    // item id or, for Star Rail and ZZZ, no gacha_id, since UIGF requires them.
+    pub fn from_history(
+        history: &GachaHistory,
+        export_timestamp: i64,
+        item_ids: &ItemIds,
+    ) -> Result<Self> {
+        let game = history.game_biz.split('_').next().unwrap_or_default();
+        let timezone = history
+            .region_time_zone
+            .or_else(|| uid_timezone(game, &history.uid))
+            .unwrap_or_else(|| {
+                log::warn!("The time zone is unknown, using UTC+{}", DEFAULT_TIMEZONE);
+                DEFAULT_TIMEZONE
+            });
+        let records = records_oldest_first(history);
+        let missing_names = records
+            .iter()
+            .filter(|record| item_id(record, item_ids).is_none())
+            .map(|record| record.name.as_str())
+            .unique()
+            .collect::<Vec<_>>();
+        if !missing_names.is_empty() {
+            bail!("Found no item id for {}", missing_names.join(", "));
+        }
+
+        let mut uigf = Uigf {
+            info: Info {
+                export_timestamp,
+                export_app: EXPORT_APP,
+                export_app_version: EXPORT_APP_VERSION,
+                version: UIGF_VERSION,
+            },
+            hk4e: vec![],
+            hkrpg: vec![],
+            nap: vec![],
+        };
+
+        match game {
+            "hk4e" => uigf.hk4e.push(Account {
+                uid: history.uid.clone(),
+                timezone,
+                lang: history.lang.clone(),
+                list: records
+                    .into_iter()
+                    .map(|record| Hk4eItem {
+                        uigf_gacha_type: uigf_gacha_type(&record.gacha_type).to_string(),
+                        gacha_type: record.gacha_type.clone(),
+                        item_id: item_id(record, item_ids).unwrap_or_default().to_string(),
+                        count: count(record),
+                        time: record.time.clone(),
+                        name: record.name.clone(),
+                        item_type: record.item_type.clone(),
+                        rank_type: record.rank_type.clone(),
+                        id: record.id.clone(),
+                    })
+                    .collect(),
+            }),
+            "hkrpg" | "nap" => {
+                let account = Account {
+                    uid: history.uid.clone(),
+                    timezone,
+                    lang: history.lang.clone(),
+                    list: records
+                        .into_iter()
+                        .map(|record| {
+                            Ok(GachaItem {
+                                gacha_id: record.gacha_id.clone().with_context(|| {
+                                    format!("Record {} has no gacha_id", record.id)
+                                })?,
+                                gacha_type: record.gacha_type.clone(),
+                                item_id: item_id(record, item_ids).unwrap_or_default().to_string(),
+                                count: count(record),
+                                time: record.time.clone(),
+                                name: record.name.clone(),
+                                item_type: record.item_type.clone(),
+                                rank_type: record.rank_type.clone(),
+                                id: record.id.clone(),
+                            })
+                        })
+                        .collect::<Result<_>>()?,
+                };
+                if game == "hkrpg" {
+                    uigf.hkrpg.push(account);
+                } else {
+                    uigf.nap.push(account);
+                }
+            }
+            _ => bail!("UIGF does not support game_biz {}", history.game_biz),
+        }
+        Ok(uigf)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::history::test_util::{self, record};
+    use crate::history::BannerHistory;
+    use serde_json::json;
+
+    fn history(game_biz: &str, banners: Vec<BannerHistory>) -> GachaHistory {
+        GachaHistory {
+            region_time_zone: Some(-5),
+            ..test_util::history(game_biz, banners)
+        }
+    }
+
+    #[test]
+    fn genshin_to_uigf() -> Result<()> {
+        let history = history(
+            "hk4e_global",
+            vec![
+                BannerHistory {
+                    gacha_type: "200".to_string(),
+                    name: "Standard Wish".to_string(),
+                    records: vec![record("10", "200")],
+                },
+                BannerHistory {
+                    gacha_type: "301".to_string(),
+                    name: "Character Event Wish".to_string(),
+                    records: vec![record("11", "400"), record("9", "301")],
+                },
+            ],
+        );
+
+        let item_ids = ItemIds::from([
+            ("Item 9".to_string(), "11509".to_string()),
+            ("Item 10".to_string(), "11510".to_string()),
+            ("Item 11".to_string(), "11511".to_string()),
+        ]);
+        let uigf = serde_json::to_value(Uigf::from_history(&history, 1700000000, &item_ids)?)?;
+        assert_eq!("v4.0", uigf["info"]["version"]);
+        assert_eq!(1700000000, uigf["info"]["export_timestamp"]);
+        assert!(uigf.get("hkrpg").is_none());
+        assert!(uigf.get("nap").is_none());
+
+        let account = &uigf["hk4e"][0];
+        assert_eq!("800000000", account["uid"]);
+        assert_eq!(-5, account["timezone"]);
+        assert_eq!("en-us", account["lang"]);
+        // Oldest first, with 400 mapped to 301.
+        assert_eq!(
+            json!([
+                {"uigf_gacha_type": "301", "gacha_type": "301", "count": "1",
+                 "time": "2024-01-01 00:00:00", "name": "Item 9", "item_type": "Weapon",
+                 "rank_type": "3", "id": "9", "item_id": "11509"},
+                {"uigf_gacha_type": "200", "gacha_type": "200", "count": "1",
+                 "time": "2024-01-01 00:00:00", "name": "Item 10", "item_type": "Weapon",
+                 "rank_type": "3", "id": "10", "item_id": "11510"},
+                {"uigf_gacha_type": "301", "gacha_type": "400", "count": "1",
+                 "time": "2024-01-01 00:00:00", "name": "Item 11", "item_type": "Weapon",
+                 "rank_type": "3", "id": "11", "item_id": "11511"},
+            ]),
+            account["list"]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn star_rail_to_uigf() -> Result<()> {
+        let history = history(
+            "hkrpg_global",
+            vec![BannerHistory {
+                gacha_type: "11".to_string(),
+                name: "Character Event Warp".to_string(),
+                records: vec![GachaRecord {
+                    gacha_id: Some("2001".to_string()),
+                    item_id: "1001".to_string(),
+                    ..record("1", "11")
+                }],
+            }],
+        );
+
+        let uigf =
+            serde_json::to_value(Uigf::from_history(&history, 1700000000, &ItemIds::new())?)?;
+        assert!(uigf.get("hk4e").is_none());
+        assert_eq!("2001", uigf["hkrpg"][0]["list"][0]["gacha_id"]);
+        assert_eq!("1001", uigf["hkrpg"][0]["list"][0]["item_id"]);
+        assert!(uigf["hkrpg"][0]["list"][0].get("uigf_gacha_type").is_none());
+        Ok(())
+    }
+
+    // Genshin's API does not return the time zone, so it is told by the UID.
+    #[test]
+    fn genshin_timezone_from_uid() -> Result<()> {
+        let timezone = |uid: &str| -> Result<serde_json::Value> {
+            let history = GachaHistory {
+                uid: uid.to_string(),
+                ..test_util::history("hk4e_global", vec![])
+            };
+            let uigf =
+                serde_json::to_value(Uigf::from_history(&history, 1700000000, &ItemIds::new())?)?;
+            Ok(uigf["hk4e"][0]["timezone"].clone())
+        };
+        assert_eq!(-5, timezone("600000000")?);
+        assert_eq!(1, timezone("700000000")?);
+        assert_eq!(8, timezone("800000000")?);
+        assert_eq!(8, timezone("100000000")?);
+        // 10 digit UIDs have the region in the second digit.
+        assert_eq!(-5, timezone("1600000000")?);
+        assert_eq!(8, timezone("1800000000")?);
+        Ok(())
+    }
+
+    // UIGF requires the item ids, so the export fails instead of leaving them out.
+    #[test]
+    fn genshin_item_id_is_required() -> Result<()> {
+        let history = history(
+            "hk4e_global",
+            vec![test_util::banner(
+                "200",
+                vec![record("1", "200"), record("2", "200")],
+            )],
+        );
+        let item_ids = ItemIds::from([("Item 1".to_string(), "11501".to_string())]);
+        let error = Uigf::from_history(&history, 1700000000, &item_ids).unwrap_err();
+        assert_eq!("Found no item id for Item 2", error.to_string());
+        Ok(())
+    }
+
+    #[test]
+    fn load_item_ids_of_numbers_and_strings() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("en.json");
+        fs::write(&path, r#"{"Diluc": 10000016, "Jean": "10000003"}"#)?;
+        let item_ids = load_item_ids(&path)?;
+        assert_eq!(Some(&"10000016".to_string()), item_ids.get("Diluc"));
+        assert_eq!(Some(&"10000003".to_string()), item_ids.get("Jean"));
+        Ok(())
+    }
+
+    #[test]
+    fn star_rail_gacha_id_is_required() {
+        let record = GachaRecord {
+            item_id: "1001".to_string(),
+            ..record("1", "11")
+        };
+        let history = history("hkrpg_global", vec![test_util::banner("11", vec![record])]);
+        let error = Uigf::from_history(&history, 1700000000, &ItemIds::new()).unwrap_err();
+        assert_eq!("Record 1 has no gacha_id", error.to_string());
+    }
+
+    #[test]
+    fn zzz_to_uigf() -> Result<()> {
+        let history = history("nap_cn", vec![]);
+        let uigf =
+            serde_json::to_value(Uigf::from_history(&history, 1700000000, &ItemIds::new())?)?;
+        assert_eq!("800000000", uigf["nap"][0]["uid"]);
+        Ok(())
+    }
+
+    #[test]
+    fn unknown_game_biz() {
+        let history = history("unknown_global", vec![]);
+        assert!(Uigf::from_history(&history, 1700000000, &ItemIds::new()).is_err());
+    }
+}
@@ -0,0 +1,183 @@
+// Checks that a gacha URL works by requesting the gacha log API with it.
+
+use crate::error::{Error, Result};
+use crate::games::{self, Validation};
+use reqwest::Url;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+
+// The HTTP transport of the validators. Embedders can supply their own, e.g. to use a proxy or
+// custom TLS settings, and tests can supply a fake one.
+pub trait HttpClient: Send + Sync {
+    // Sends a GET request to |url| and returns the response body.
+    fn get(&self, url: &Url) -> Result<String>;
+}
+
+impl HttpClient for reqwest::blocking::Client {
+    fn get(&self, url: &Url) -> Result<String> {
+        Ok(self
+            .get(url.clone())
+            .header("Content-Type", "application/json")
+            .send()?
+            .text()?)
+    }
+}
+
+// Checks whether a gacha URL works. Since the testing could transform the URL, it returns a
+// String on success.
+pub trait UrlValidator {
+    fn validate(&self, client: &dyn HttpClient, url: &str) -> Result<String>;
+
+    // Transforms |url| in the same way as validate() does, without checking it.
+    fn normalize(&self, url: &str) -> Result<String>;
+}
+
+// Checks |url| in the way described by the game definition.
+impl UrlValidator for Validation {
+    fn validate(&self, client: &dyn HttpClient, url: &str) -> Result<String> {
+        match self {
+            Validation::WishPage {
+                api_host,
+                api_path,
+                query_overrides,
+            } => test_genshin_wish_url(client, url, api_host, api_path, query_overrides),
+            Validation::GachaLogApi => test_gacha_log_api_url(client, url),
+        }
+    }
+
+    fn normalize(&self, url: &str) -> Result<String> {
+        match self {
+            // The wish page URL is used as is.
+            Validation::WishPage { .. } => Ok(url.to_string()),
+            Validation::GachaLogApi => normalize_gacha_log_api_url(url),
+        }
+    }
+}
+
+// Validates each URL of |urls| with its validation, at most |parallelism| at the same time, and
+// returns the results in the same order.
+pub fn validate_all(
+    client: &dyn HttpClient,
+    urls: &[(&Validation, &str)],
+    parallelism: usize,
+) -> Vec<Result<String>> {
+    let next_index = AtomicUsize::new(0);
+    let results = Mutex::new(urls.iter().map(|_| None).collect::<Vec<_>>());
+
+    thread::scope(|scope| {
+        for _ in 0..parallelism.max(1).min(urls.len()) {
+            scope.spawn(|| loop {
+                let index = next_index.fetch_add(1, Ordering::SeqCst);
+                let Some((validation, url)) = urls.get(index) else {
+                    break;
+                };
+                let result = validation.validate(client, url);
+                results.lock().unwrap()[index] = Some(result);
+            });
+        }
+    });
+
+    results
+        .into_inner()
+        .unwrap()
+        .into_iter()
+        // Every index is taken by a worker before the workers stop.
+        .map(|result| result.expect("URL was not validated"))
+        .collect()
+}
+
+pub fn test_genshin_wish_url(
+    client: &dyn HttpClient,
+    url: &str,
+    api_host: &str,
+    api_path: &str,
+    query_overrides: &BTreeMap<String, String>,
+) -> Result<String> {
+    log::debug!("Checking genshin wish url: {}", url);
+    let uri = games::wish_page_api_url(url, api_host, api_path, query_overrides).map_err(|e| {
+        Error::InvalidUrl {
+            url: url.to_string(),
+            reason: e.to_string(),
+        }
+    })?;
+
+    check_response(&uri, &client.get(&uri)?)?;
+    Ok(url.to_string())
+}
+
+// ZZZ and Star Rail cache the getGachaLog API URL itself, so the URL can be requested as is.
+// On success, the URL is returned with only the parameters needed to fetch the logs.
+pub fn test_gacha_log_api_url(client: &dyn HttpClient, url: &str) -> Result<String> {
+    log::debug!("Checking gacha log api url: {}", url);
+
+    let invalid_url = |reason: &str| Error::InvalidUrl {
+        url: url.to_string(),
+        reason: reason.to_string(),
+    };
+
+    let parsed_url = Url::parse(url).map_err(|e| invalid_url(&e.to_string()))?;
+    check_response(&parsed_url, &client.get(&parsed_url)?)?;
+    normalize_gacha_log_api_url(url)
+}
+
+// Returns |url| with only the parameters needed to fetch the logs.
+pub fn normalize_gacha_log_api_url(url: &str) -> Result<String> {
+    let invalid_url = |reason: &str| Error::InvalidUrl {
+        url: url.to_string(),
+        reason: reason.to_string(),
+    };
+
+    let parsed_url = Url::parse(url).map_err(|e| invalid_url(&e.to_string()))?;
+    let mut query_params: Vec<(String, String)> = parsed_url.query_pairs().into_owned().collect();
+    const KEYS_TO_KEEP: [&str; 5] = ["authkey", "authkey_ver", "sign_type", "game_biz", "lang"];
+    query_params.retain(|(key, _)| KEYS_TO_KEEP.contains(&key.as_str()));
+
+    Ok(format!(
+        "{}://{}{}?{}",
+        parsed_url.scheme(),
+        parsed_url
+            .host()
+            .ok_or_else(|| invalid_url("Cannot find host in URL"))?,
+        parsed_url.path(),
+        serde_urlencoded::to_string(&query_params).map_err(|e| invalid_url(&e.to_string()))?
+    ))
+}
+
+// Checks the retcode in the JSON |body| of the response from |url|, and returns the parsed
+// response. Fetching the history checks its responses with this too, so that the retcodes are
+// classified the same way everywhere.
+pub(crate) fn check_response(url: &Url, body: &str) -> Result<Value> {
+    let invalid_response = |reason: &str| Error::InvalidResponse {
+        url: url.to_string(),
+        reason: reason.to_string(),
+    };
+    let response =
+        serde_json::from_str::<Value>(body).map_err(|e| invalid_response(&e.to_string()))?;
+
+    const RETURN_CODE_FIELD_NAME: &str = "retcode";
+    let retcode = response
+        .get(RETURN_CODE_FIELD_NAME)
+        .ok_or_else(|| invalid_response("Response JSON did not contain a retcode field"))?;
+
+    log::debug!("{} contained: {}", RETURN_CODE_FIELD_NAME, retcode);
+
+    let retcode = retcode
+        .as_i64()
+        .ok_or_else(|| invalid_response("retcode is not a number"))?;
+    let message = response
+        .get("message")
+        .and_then(Value::as_str)
+        .unwrap_or_default()
+        .to_string();
+    match retcode {
+        0 => Ok(response),
+        -100 => Err(Error::AuthkeyInvalid { message }),
+        -101 => Err(Error::AuthkeyExpired { message }),
+        -110 => Err(Error::RateLimited { message }),
+        -111 => Err(Error::WrongGame { message }),
+        retcode => Err(Error::Api { retcode, message }),
+    }
+}
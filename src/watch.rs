@@ -0,0 +1,232 @@
+// Watches the web caches of a game install, to find a new URL as soon as the gacha history is
+// opened in game.
+
+use crate::extractor;
+use anyhow::{Context, Result};
+use notify::{Event, PollWatcher, RecommendedWatcher, RecursiveMode, Watcher};
+use std::path::{Component, Path, PathBuf};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::time::{Duration, Instant};
+
+// The game writes a cache entry in many small writes, so a change is only reported once the cache
+// has not changed for this long.
+const DEFAULT_DEBOUNCE: Duration = Duration::from_secs(2);
+// Shorter than the debounce, so that polling sees the writes before the debounce ends.
+pub const DEFAULT_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+const CACHE_DATA_DIR_NAME: &str = "Cache_Data";
+
+pub struct CacheWatcher {
+    web_cache_dir: PathBuf,
+    // Kept to keep watching. Dropping it stops the events.
+    _watcher: Box<dyn Watcher + Send>,
+    receiver: mpsc::Receiver<notify::Result<Event>>,
+    debounce: Duration,
+}
+
+impl CacheWatcher {
+    // Watches |web_cache_dir| with the native watcher of the platform, e.g. inotify. Falls back to
+    // polling when the native watcher fails, e.g. when inotify runs out of watches.
+    pub fn new(web_cache_dir: &Path) -> Result<Self> {
+        let (sender, receiver) = mpsc::channel();
+        let native = RecommendedWatcher::new(sender, notify::Config::default())
+            .map(|watcher| Box::new(watcher) as Box<dyn Watcher + Send>)
+            .and_then(|watcher| Self::watch(watcher, web_cache_dir));
+        match native {
+            Ok(watcher) => Ok(Self::with_watcher(web_cache_dir, watcher, receiver)),
+            Err(e) => {
+                log::debug!("Failed to watch natively, polling instead: {}", e);
+                Self::polling(web_cache_dir, DEFAULT_POLL_INTERVAL)
+            }
+        }
+    }
+
+    // Watches |web_cache_dir| by scanning it every |interval|. Works where the native watcher does
+    // not report changes, e.g. on network file systems.
+    pub fn polling(web_cache_dir: &Path, interval: Duration) -> Result<Self> {
+        let (sender, receiver) = mpsc::channel();
+        let config = notify::Config::default().with_poll_interval(interval);
+        let watcher = Box::new(PollWatcher::new(sender, config)?);
+        let watcher = Self::watch(watcher, web_cache_dir)
+            .with_context(|| format!("Failed to watch {}", web_cache_dir.display()))?;
+        Ok(Self::with_watcher(web_cache_dir, watcher, receiver))
+    }
+
+    // Sets how long the cache has to stay unchanged before a change is reported.
+    pub fn with_debounce(mut self, debounce: Duration) -> Self {
+        self.debounce = debounce;
+        self
+    }
+
+    // Blocks until a change that can bring a new URL, and then until the cache stops changing.
+    // Only the relevant changes delay the end, since e.g. GPUCache keeps changing while the game
+    // runs.
+    pub fn wait_for_change(&self) -> Result<()> {
+        loop {
+            let event = self.receiver.recv().context("Stopped watching the cache")?;
+            if self.is_relevant_event(&event) {
+                break;
+            }
+        }
+        let mut deadline = Instant::now() + self.debounce;
+        loop {
+            match self
+                .receiver
+                .recv_timeout(deadline.saturating_duration_since(Instant::now()))
+            {
+                Ok(event) => {
+                    if self.is_relevant_event(&event) {
+                        deadline = Instant::now() + self.debounce;
+                    }
+                }
+                Err(RecvTimeoutError::Timeout) => return Ok(()),
+                Err(RecvTimeoutError::Disconnected) => {
+                    anyhow::bail!("Stopped watching the cache")
+                }
+            }
+        }
+    }
+
+    fn with_watcher(
+        web_cache_dir: &Path,
+        watcher: Box<dyn Watcher + Send>,
+        receiver: mpsc::Receiver<notify::Result<Event>>,
+    ) -> Self {
+        Self {
+            web_cache_dir: web_cache_dir.to_path_buf(),
+            _watcher: watcher,
+            receiver,
+            debounce: DEFAULT_DEBOUNCE,
+        }
+    }
+
+    fn watch(
+        mut watcher: Box<dyn Watcher + Send>,
+        web_cache_dir: &Path,
+    ) -> notify::Result<Box<dyn Watcher + Send>> {
+        watcher.watch(web_cache_dir, RecursiveMode::Recursive)?;
+        Ok(watcher)
+    }
+
+    fn is_relevant_event(&self, event: &notify::Result<Event>) -> bool {
+        match event {
+            Ok(event) if event.paths.iter().any(|path| self.is_relevant(path)) => {
+                log::debug!("The cache changed: {:?}", event);
+                true
+            }
+            Ok(_) => false,
+            Err(e) => {
+                log::debug!("Error while watching the cache: {}", e);
+                false
+            }
+        }
+    }
+
+    // Whether a change of |path| can bring a new URL. These are the changes in the cache
+    // directories, e.g. of data_2, and new versioned directories. The other directories in
+    // webCaches, e.g. GPUCache, do not have the URLs.
+    fn is_relevant(&self, path: &Path) -> bool {
+        let Ok(relative_path) = path.strip_prefix(&self.web_cache_dir) else {
+            return false;
+        };
+        let components = relative_path
+            .components()
+            .filter_map(|component| match component {
+                Component::Normal(name) => name.to_str(),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        match components.as_slice() {
+            [versioned_dir] => extractor::is_versioned_dir_name(versioned_dir),
+            [versioned_dir, rest @ ..] => {
+                extractor::is_versioned_dir_name(versioned_dir)
+                    && rest.contains(&CACHE_DATA_DIR_NAME)
+            }
+            [] => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+    use std::thread;
+    use tempfile::tempdir;
+
+    #[test]
+    fn relevant_changes() -> Result<()> {
+        let dir = tempdir()?;
+        let watcher = CacheWatcher::polling(dir.path(), Duration::from_secs(60))?;
+        let cache_data = dir.path().join("2.5.0.0").join("Cache").join("Cache_Data");
+
+        assert!(watcher.is_relevant(&cache_data.join("data_2")));
+        assert!(watcher.is_relevant(&cache_data.join("f_000001")));
+        assert!(watcher.is_relevant(&dir.path().join("2.6.0.0")));
+        assert!(!watcher.is_relevant(&dir.path().join("2.5.0.0").join("GPUCache")));
+        assert!(!watcher.is_relevant(&dir.path().join("not_a_version")));
+        assert!(!watcher.is_relevant(dir.path()));
+        Ok(())
+    }
+
+    // A burst of writes is reported once the cache stops changing. The debounce is much longer
+    // than the gaps between the writes, so that a slow machine does not end it early.
+    #[test]
+    fn change_is_debounced() -> Result<()> {
+        let dir = tempdir()?;
+        let cache_data = dir.path().join("2.5.0.0").join("Cache").join("Cache_Data");
+        std::fs::create_dir_all(&cache_data)?;
+        std::fs::write(cache_data.join("data_2"), "")?;
+        let watcher = CacheWatcher::polling(dir.path(), Duration::from_millis(20))?
+            .with_debounce(Duration::from_secs(1));
+
+        // New files, since the polling only sees changes of the modification time in seconds.
+        let writer = thread::spawn(move || {
+            for i in 0..5 {
+                thread::sleep(Duration::from_millis(50));
+                std::fs::write(cache_data.join(format!("f_{:06}", i)), "entry").unwrap();
+            }
+        });
+        watcher.wait_for_change()?;
+        assert!(writer.is_finished());
+        writer.join().unwrap();
+        Ok(())
+    }
+
+    // Changes of GPUCache do not keep the debounce from ending.
+    #[test]
+    fn irrelevant_changes_do_not_extend_debounce() -> Result<()> {
+        let dir = tempdir()?;
+        let versioned_dir = dir.path().join("2.5.0.0");
+        let cache_data = versioned_dir.join("Cache").join("Cache_Data");
+        let gpu_cache = versioned_dir.join("GPUCache");
+        std::fs::create_dir_all(&cache_data)?;
+        std::fs::create_dir_all(&gpu_cache)?;
+        let watcher = CacheWatcher::polling(dir.path(), Duration::from_millis(20))?
+            .with_debounce(Duration::from_millis(300));
+
+        let stop = Arc::new(AtomicBool::new(false));
+        let writer = thread::spawn({
+            let stop = stop.clone();
+            move || {
+                thread::sleep(Duration::from_millis(50));
+                std::fs::write(cache_data.join("f_000000"), "entry").unwrap();
+                let start = Instant::now();
+                let mut i = 0;
+                while !stop.load(Ordering::Relaxed) && start.elapsed() < Duration::from_secs(10) {
+                    thread::sleep(Duration::from_millis(50));
+                    std::fs::write(gpu_cache.join(format!("f_{:06}", i)), "entry").unwrap();
+                    i += 1;
+                }
+            }
+        });
+        let start = Instant::now();
+        watcher.wait_for_change()?;
+        // The writes to GPUCache go on for 10s if they extend the debounce.
+        assert!(start.elapsed() < Duration::from_secs(5));
+        stop.store(true, Ordering::Relaxed);
+        writer.join().unwrap();
+        Ok(())
+    }
+}
@@ -0,0 +1,23 @@
+use extract_wish_url::validate::HttpClient;
+use extract_wish_url::Error;
+use reqwest::Url;
+
+// Sends requests to https://127.0.0.1 over HTTP, so that URLs found in the cache can be served by
+// mockito.
+pub struct LocalhostClient(pub reqwest::blocking::Client);
+
+impl LocalhostClient {
+    pub fn new() -> Self {
+        Self(reqwest::blocking::Client::new())
+    }
+}
+
+impl HttpClient for LocalhostClient {
+    fn get(&self, url: &Url) -> Result<String, Error> {
+        let mut url = url.clone();
+        if url.scheme() == "https" && url.host_str() == Some("127.0.0.1") {
+            url.set_scheme("http").unwrap();
+        }
+        HttpClient::get(&self.0, &url)
+    }
+}
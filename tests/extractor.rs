@@ -0,0 +1,454 @@
+mod common;
+
+use anyhow::Result;
+use common::LocalhostClient;
+use extract_wish_url::games::Region;
+use extract_wish_url::{Error, GameRegistry, PullExtractor};
+use std::io::{BufWriter, Write};
+use tempfile::tempdir;
+
+#[test]
+fn test_genshin_pull_extractor_new() -> Result<()> {
+    let dir = tempdir()?;
+    let cache_data_dir = dir
+        .path()
+        .join("GenshinImpact_Data")
+        .join("webCaches")
+        .join("4.5.6.7")
+        .join("Cache")
+        .join("Cache_Data");
+    std::fs::create_dir_all(&cache_data_dir)?;
+    std::fs::File::create(cache_data_dir.join("data_2"))?;
+    PullExtractor::new(dir.path())?;
+    Ok(())
+}
+
+#[test]
+fn test_zzz_pull_extractor_new() -> Result<()> {
+    let dir = tempdir()?;
+    let cache_data_dir = dir
+        .path()
+        .join("ZenlessZoneZero_Data")
+        .join("webCaches")
+        .join("4.5.6.7")
+        .join("Cache")
+        .join("Cache_Data");
+    std::fs::create_dir_all(&cache_data_dir)?;
+    std::fs::File::create(cache_data_dir.join("data_2"))?;
+    PullExtractor::new(dir.path())?;
+    Ok(())
+}
+
+#[test]
+fn test_star_rail_pull_extractor_new() -> Result<()> {
+    let dir = tempdir()?;
+    let cache_data_dir = dir
+        .path()
+        .join("StarRail_Data")
+        .join("webCaches")
+        .join("2.5.0.0")
+        .join("Cache")
+        .join("Cache_Data");
+    std::fs::create_dir_all(&cache_data_dir)?;
+    std::fs::File::create(cache_data_dir.join("data_2"))?;
+    PullExtractor::new(dir.path())?;
+    Ok(())
+}
+
+#[test]
+fn test_genshin_cn_pull_extractor_new() -> Result<()> {
+    let dir = tempdir()?;
+    let cache_data_dir = dir
+        .path()
+        .join("YuanShen_Data")
+        .join("webCaches")
+        .join("4.5.6.7")
+        .join("Cache")
+        .join("Cache_Data");
+    std::fs::create_dir_all(&cache_data_dir)?;
+    std::fs::File::create(cache_data_dir.join("data_2"))?;
+    let extractor = PullExtractor::new(dir.path())?;
+    assert_eq!(1, extractor.game_types().len());
+    assert_eq!(Region::China, extractor.game_types()[0].region);
+    Ok(())
+}
+
+#[test]
+fn test_zzz_pull_extractor_extract() -> Result<()> {
+    let dir = tempdir()?;
+    let cache_data_dir = dir
+        .path()
+        .join("ZenlessZoneZero_Data")
+        .join("webCaches")
+        .join("4.5.6.7")
+        .join("Cache")
+        .join("Cache_Data");
+    std::fs::create_dir_all(&cache_data_dir)?;
+    let data_2_file = std::fs::File::create(cache_data_dir.join("data_2"))?;
+    let extractor = PullExtractor::new(dir.path())?.with_http_client(LocalhostClient::new());
+
+    let mut server = mockito::Server::new();
+    let url= format!("{}{}{}",
+        "https://", 
+        &server.host_with_port(),
+        // Note that these include the required params.
+        "/getGachaLog/index.html?lang=en&extraparam=1234&authkey=key&authkey_ver=2&sign_type=sometype&game_biz=nap_global");
+
+    let mut writer = BufWriter::new(data_2_file);
+    writer.write_all(url.as_bytes())?;
+    writer.flush()?;
+
+    // Create a mock
+    let mock = server
+        // This path matches the above.
+        .mock("GET", "/getGachaLog/index.html?lang=en&extraparam=1234&authkey=key&authkey_ver=2&sign_type=sometype&game_biz=nap_global")
+        .with_status(200)
+        .with_header("content-type", "application/json")
+        // Retcode is -1! The function should return an error.
+        .with_body(r#"{"retcode": 0}"#)
+        .create();
+
+    let result = extractor.extract_url()?;
+    assert_eq!("https://127.0.0.1/getGachaLog/index.html?lang=en&authkey=key&authkey_ver=2&sign_type=sometype&game_biz=nap_global", result.url);
+    mock.assert();
+
+    Ok(())
+}
+
+#[test]
+fn test_zzz_cn_pull_extractor_extract() -> Result<()> {
+    let dir = tempdir()?;
+    let cache_data_dir = dir
+        .path()
+        .join("ZenlessZoneZero_Data")
+        .join("webCaches")
+        .join("4.5.6.7")
+        .join("Cache")
+        .join("Cache_Data");
+    std::fs::create_dir_all(&cache_data_dir)?;
+    let data_2_file = std::fs::File::create(cache_data_dir.join("data_2"))?;
+    let extractor = PullExtractor::new(dir.path())?.with_http_client(LocalhostClient::new());
+
+    let mut server = mockito::Server::new();
+    let url= format!("{}{}{}",
+        "https://", 
+        &server.host_with_port(),
+        // Note that these include the required params.
+        "/getGachaLog/index.html?lang=en&extraparam=1234&authkey=key&authkey_ver=2&sign_type=sometype&game_biz=nap_cn");
+
+    let mut writer = BufWriter::new(data_2_file);
+    writer.write_all(url.as_bytes())?;
+    writer.flush()?;
+
+    // Create a mock
+    let mock = server
+        // This path matches the above.
+        .mock("GET", "/getGachaLog/index.html?lang=en&extraparam=1234&authkey=key&authkey_ver=2&sign_type=sometype&game_biz=nap_cn")
+        .with_status(200)
+        .with_header("content-type", "application/json")
+        .with_body(r#"{"retcode": 0}"#)
+        .create();
+
+    let result = extractor.extract_url()?;
+    assert_eq!("https://127.0.0.1/getGachaLog/index.html?lang=en&authkey=key&authkey_ver=2&sign_type=sometype&game_biz=nap_cn", result.url);
+    mock.assert();
+
+    Ok(())
+}
+
+// After a game update, the newest versioned directory may not have the URL yet.
+#[test]
+fn test_pull_extractor_extract_from_older_version() -> Result<()> {
+    let dir = tempdir()?;
+    let web_cache_dir = dir.path().join("ZenlessZoneZero_Data").join("webCaches");
+    let older_cache = web_cache_dir
+        .join("1.2.3.4")
+        .join("Cache")
+        .join("Cache_Data");
+    let newer_cache = web_cache_dir
+        .join("1.3.0.0")
+        .join("Cache")
+        .join("Cache_Data");
+    std::fs::create_dir_all(&older_cache)?;
+    std::fs::create_dir_all(&newer_cache)?;
+    std::fs::File::create(newer_cache.join("data_2"))?;
+
+    let mut server = mockito::Server::new();
+    let url = format!(
+        "https://{}/getGachaLog?authkey=key&game_biz=nap_global",
+        &server.host_with_port()
+    );
+    std::fs::write(older_cache.join("data_2"), url.as_bytes())?;
+
+    let mock = server
+        .mock("GET", "/getGachaLog?authkey=key&game_biz=nap_global")
+        .with_status(200)
+        .with_body(r#"{"retcode": 0}"#)
+        .create();
+
+    let extractor = PullExtractor::new(dir.path())?.with_http_client(LocalhostClient::new());
+    let result = extractor.extract_url()?;
+    assert_eq!(
+        "https://127.0.0.1/getGachaLog?authkey=key&game_biz=nap_global",
+        result.url
+    );
+    assert_eq!(web_cache_dir.join("1.2.3.4"), result.source_dir);
+    mock.assert();
+    Ok(())
+}
+
+#[test]
+fn test_pull_extractor_new_no_data_dir() -> Result<()> {
+    let dir = tempdir()?;
+    assert!(matches!(
+        PullExtractor::new(dir.path()),
+        Err(Error::DataDirNotFound(_))
+    ));
+    Ok(())
+}
+
+#[test]
+fn test_pull_extractor_extract_no_urls() -> Result<()> {
+    let dir = tempdir()?;
+    let cache_data_dir = dir
+        .path()
+        .join("StarRail_Data")
+        .join("webCaches")
+        .join("2.5.0.0")
+        .join("Cache")
+        .join("Cache_Data");
+    std::fs::create_dir_all(&cache_data_dir)?;
+    std::fs::write(cache_data_dir.join("data_2"), b"no urls here")?;
+    let extractor = PullExtractor::new(dir.path())?;
+    assert!(matches!(extractor.extract_url(), Err(Error::NoUrlsFound)));
+    Ok(())
+}
+
+// When the API rate limits the requests, the other URLs are not tried.
+#[test]
+fn test_pull_extractor_extract_rate_limited() -> Result<()> {
+    let dir = tempdir()?;
+    let cache_data_dir = dir
+        .path()
+        .join("ZenlessZoneZero_Data")
+        .join("webCaches")
+        .join("1.2.3.4")
+        .join("Cache")
+        .join("Cache_Data");
+    std::fs::create_dir_all(&cache_data_dir)?;
+
+    let mut server = mockito::Server::new();
+    let first_url = format!(
+        "https://{}/getGachaLog?authkey=first&game_biz=nap_global",
+        &server.host_with_port()
+    );
+    let second_url = format!(
+        "https://{}/getGachaLog?authkey=second&game_biz=nap_global",
+        &server.host_with_port()
+    );
+    std::fs::write(
+        cache_data_dir.join("data_2"),
+        format!("{}\0{}", first_url, second_url),
+    )?;
+
+    let first_mock = server
+        .mock("GET", "/getGachaLog?authkey=first&game_biz=nap_global")
+        .with_status(200)
+        .with_body(r#"{"retcode": -110, "message": "visit too frequently"}"#)
+        .create();
+    let second_mock = server
+        .mock("GET", "/getGachaLog?authkey=second&game_biz=nap_global")
+        .with_status(200)
+        .with_body(r#"{"retcode": 0}"#)
+        .expect(0)
+        .create();
+
+    let extractor = PullExtractor::new(dir.path())?
+        .with_http_client(LocalhostClient::new())
+        .with_parallelism(1);
+    assert!(matches!(
+        extractor.extract_url(),
+        Err(Error::RateLimited { .. })
+    ));
+    first_mock.assert();
+    second_mock.assert();
+    Ok(())
+}
+
+// URLs with the same authkey are only validated once, and the first working URL in the cache
+// wins even when the URLs are validated concurrently.
+#[test]
+fn test_pull_extractor_extract_concurrently() -> Result<()> {
+    let dir = tempdir()?;
+    let cache_data_dir = dir
+        .path()
+        .join("ZenlessZoneZero_Data")
+        .join("webCaches")
+        .join("1.2.3.4")
+        .join("Cache")
+        .join("Cache_Data");
+    std::fs::create_dir_all(&cache_data_dir)?;
+
+    let mut server = mockito::Server::new();
+    let urls = [
+        "/getGachaLog?authkey=expired&game_biz=nap_global",
+        "/getGachaLog?authkey=expired&page=2&game_biz=nap_global",
+        "/getGachaLog?authkey=working&game_biz=nap_global",
+        "/getGachaLog?authkey=also_working&game_biz=nap_global",
+    ];
+    std::fs::write(
+        cache_data_dir.join("data_2"),
+        urls.iter()
+            .map(|path| format!("https://{}{}", server.host_with_port(), path))
+            .collect::<Vec<_>>()
+            .join("\0"),
+    )?;
+
+    let expired_mock = server
+        .mock("GET", urls[0])
+        .with_status(200)
+        .with_body(r#"{"retcode": -101, "message": "authkey timeout"}"#)
+        .create();
+    let duplicate_mock = server.mock("GET", urls[1]).expect(0).create();
+    let working_mock = server
+        .mock("GET", urls[2])
+        .with_status(200)
+        .with_body(r#"{"retcode": 0}"#)
+        .create();
+    let also_working_mock = server
+        .mock("GET", urls[3])
+        .with_status(200)
+        .with_body(r#"{"retcode": 0}"#)
+        .expect_at_most(1)
+        .create();
+
+    let extractor = PullExtractor::new(dir.path())?
+        .with_http_client(LocalhostClient::new())
+        .with_parallelism(3);
+    let result = extractor.extract_url()?;
+    assert_eq!(
+        "https://127.0.0.1/getGachaLog?authkey=working&game_biz=nap_global",
+        result.url
+    );
+    expired_mock.assert();
+    duplicate_mock.assert();
+    working_mock.assert();
+    also_working_mock.assert();
+    Ok(())
+}
+
+#[test]
+fn test_pull_extractor_extract_unverified_urls() -> Result<()> {
+    let dir = tempdir()?;
+    let cache_data_dir = dir
+        .path()
+        .join("StarRail_Data")
+        .join("webCaches")
+        .join("2.5.0.0")
+        .join("Cache")
+        .join("Cache_Data");
+    std::fs::create_dir_all(&cache_data_dir)?;
+    let urls = [
+        "https://public-operation-hkrpg-sg.hoyoverse.com/common/gacha_record/api/getGachaLog?authkey=a&page=1&game_biz=hkrpg_global",
+        "https://public-operation-hkrpg-sg.hoyoverse.com/common/gacha_record/api/getGachaLog?authkey=a&page=2&game_biz=hkrpg_global",
+        "https://public-operation-hkrpg-sg.hoyoverse.com/common/gacha_record/api/getGachaLog?authkey=b&lang=en&game_biz=hkrpg_global",
+    ];
+    std::fs::write(cache_data_dir.join("data_2"), urls.join("\0"))?;
+
+    // No HTTP client is involved, so this works without network.
+    let extracted = PullExtractor::new(dir.path())?.extract_unverified_urls()?;
+    assert_eq!(
+        vec![
+            "https://public-operation-hkrpg-sg.hoyoverse.com/common/gacha_record/api/getGachaLog?authkey=a&game_biz=hkrpg_global",
+            "https://public-operation-hkrpg-sg.hoyoverse.com/common/gacha_record/api/getGachaLog?authkey=b&lang=en&game_biz=hkrpg_global",
+        ],
+        extracted.iter().map(|e| e.url.as_str()).collect::<Vec<_>>()
+    );
+    assert!(extracted.iter().all(|e| !e.verified));
+    Ok(())
+}
+
+// Every URL is validated, and the results are kept in the order of the URLs.
+#[test]
+fn test_pull_extractor_check_all_urls() -> Result<()> {
+    let dir = tempdir()?;
+    let cache_data_dir = dir
+        .path()
+        .join("ZenlessZoneZero_Data")
+        .join("webCaches")
+        .join("1.2.3.4")
+        .join("Cache")
+        .join("Cache_Data");
+    std::fs::create_dir_all(&cache_data_dir)?;
+
+    let mut server = mockito::Server::new();
+    let urls = [
+        "/getGachaLog?authkey=expired&game_biz=nap_global",
+        "/getGachaLog?authkey=working&game_biz=nap_global",
+        "/getGachaLog?authkey=also_expired&game_biz=nap_global",
+    ];
+    std::fs::write(
+        cache_data_dir.join("data_2"),
+        urls.iter()
+            .map(|path| format!("https://{}{}", server.host_with_port(), path))
+            .collect::<Vec<_>>()
+            .join("\0"),
+    )?;
+    let mocks = [
+        r#"{"retcode": -101, "message": "authkey timeout"}"#,
+        r#"{"retcode": 0}"#,
+        r#"{"retcode": -101, "message": "authkey timeout"}"#,
+    ]
+    .iter()
+    .zip(urls)
+    .map(|(body, url)| server.mock("GET", url).with_body(*body).create())
+    .collect::<Vec<_>>();
+
+    let checked = PullExtractor::new(dir.path())?
+        .with_http_client(LocalhostClient::new())
+        .check_all_urls()?;
+    assert_eq!(
+        vec![false, true, false],
+        checked
+            .iter()
+            .map(|checked| checked.extracted.verified)
+            .collect::<Vec<_>>()
+    );
+    assert!(matches!(
+        checked[0].error,
+        Some(Error::AuthkeyExpired { .. })
+    ));
+    assert!(checked[1].error.is_none());
+    assert_eq!(
+        "https://127.0.0.1/getGachaLog?authkey=working&game_biz=nap_global",
+        checked[1].extracted.url
+    );
+    for mock in mocks {
+        mock.assert();
+    }
+    Ok(())
+}
+
+// A data_2 file outside of a game install is searched for the URLs of every game.
+#[test]
+fn test_pull_extractor_from_data2_file() -> Result<()> {
+    let dir = tempdir()?;
+    let data2_path = dir.path().join("data_2.copy");
+    std::fs::write(
+        &data2_path,
+        "https://public-operation-hkrpg-sg.hoyoverse.com/common/gacha_record/api/getGachaLog?authkey=a&game_biz=hkrpg_global",
+    )?;
+
+    let extracted = PullExtractor::from_data2_file(&data2_path, GameRegistry::builtin())
+        .extract_unverified_urls()?;
+    assert_eq!(1, extracted.len());
+    assert_eq!("hkrpg_global", extracted[0].game_type.game_biz().unwrap());
+    assert_eq!(dir.path(), extracted[0].source_dir);
+
+    assert!(matches!(
+        PullExtractor::from_data2_file(&dir.path().join("missing"), GameRegistry::builtin())
+            .extract_unverified_urls(),
+        Err(Error::NoCacheData(_))
+    ));
+    Ok(())
+}
@@ -0,0 +1,199 @@
+use anyhow::Result;
+use extract_wish_url::extractor::{find_gacha_urls_in_slice, get_cache_dirs, scan_for_urls};
+use extract_wish_url::{Error, GameRegistry};
+use tempfile::tempdir;
+
+// Verify it can find the URL in binary data.
+#[test]
+fn find_gacha_url_only_url() {
+    let test_url = "https://gs.hoyoverse.com/genshin/event/e20190909gacha-v3/index.html?anythinghere&game_biz=hk4e_global";
+
+    // any data.
+    let mut test_data: Vec<u8> = vec![2, 8, 11, 22, 93];
+    test_data.extend_from_slice(test_url.as_bytes());
+    // More irrelevant data at end.
+    test_data.extend_from_slice(&[43, 100, 65, 2, 1, 4, 73]);
+
+    let result = find_gacha_urls_in_slice(
+        &test_data,
+        "gacha-v3",
+        "https://gs.hoyoverse.com/",
+        "game_biz=hk4e_global",
+    );
+    assert!(result.is_ok());
+    let result = result.unwrap();
+    assert_eq!(1, result.len());
+    assert_eq!(test_url, result[0]);
+}
+
+#[test]
+fn find_gacha_url() {
+    let test_url = "https://gs.hoyoverse.com/genshin/event/e20190909gacha-v3/index.html?anythinghere&game_biz=hk4e_global";
+    let test_url_vec = test_url.as_bytes().to_vec();
+
+    let result = find_gacha_urls_in_slice(
+        &test_url_vec,
+        "gacha-v3",
+        "https://gs.hoyoverse.com/",
+        "game_biz=hk4e_global",
+    );
+    assert!(result.is_ok());
+    let result = result.unwrap();
+    assert_eq!(1, result.len());
+    assert_eq!(test_url, result[0]);
+}
+
+// Verify that it can find multiple urls.
+#[test]
+fn find_gacha_urls_in_ascii() {
+    let test_url1 = "https://gs.hoyoverse.com/genshin/event/e20190909gacha-v3/index.html?ANYDATA11111&game_biz=hk4e_global";
+    let test_url2 = "https://gs.hoyoverse.com/genshin/event/e20190909gacha-v3/index.html?DIFFERTDATA22222&game_biz=hk4e_global";
+
+    let test_data: Vec<u8> = [
+        test_url1.as_bytes(),
+        // any data.
+        &[0xFF, 0x00, 0x3A, 0xBC],
+        test_url2.as_bytes(),
+    ]
+    .concat();
+
+    let result = find_gacha_urls_in_slice(
+        &test_data,
+        "gacha-v3",
+        "https://gs.hoyoverse.com/",
+        "game_biz=hk4e_global",
+    );
+    assert!(result.is_ok());
+    let result = result.unwrap();
+    assert_eq!(2, result.len());
+    assert_eq!(test_url1, result[0]);
+    assert_eq!(test_url2, result[1]);
+}
+
+// gacha-v3 marker is in the url but cannot find the end.
+#[test]
+fn no_gacha_url_has_marker_no_end_marker() {
+    let test_url =
+        "https://gs.hoyoverse.com/genshin/event/e20190909gacha-v3/index.html?anythinghere";
+    let test_url_vec = test_url.as_bytes().to_vec();
+    let result = find_gacha_urls_in_slice(
+        &test_url_vec,
+        "gacha-v3",
+        "https://gs.hoyoverse.com/",
+        "game_biz=hk4e_global",
+    );
+    assert!(result.is_err());
+}
+
+// gacha-v3 marker and game_biz=hk4e_global are present but cannot find https:// start.
+#[test]
+fn no_gacha_url_has_marker_has_end_marker_no_start_marker() {
+    let test_url = "verse.com/genshin/event/e20190909gacha-v3/index.html?anythinghere";
+    let test_url_vec = test_url.as_bytes().to_vec();
+    let result = find_gacha_urls_in_slice(
+        &test_url_vec,
+        "gacha-v3",
+        "https://gs.hoyoverse.com/",
+        "game_biz=hk4e_global",
+    );
+    assert!(result.is_err());
+}
+
+// The URLs of every game are found in any content, e.g. a HAR export, and each URL is reported
+// once, with its game.
+#[test]
+fn scan_for_urls_of_all_games() {
+    let star_rail_url = "https://public-operation-hkrpg-sg.hoyoverse.com/common/gacha_record/api/getGachaLog?authkey=a&game_biz=hkrpg_global";
+    let genshin_cn_url = "https://webstatic.mihoyo.com/hk4e/event/e20190909gacha-v3/index.html?webview_gacha&authkey=b&game_biz=hk4e_cn";
+    let har = format!(
+        r#"{{"log": {{"entries": [
+            {{"request": {{"url": "{star_rail_url}"}}}},
+            {{"request": {{"url": "https://example.com/js/getGachaLog.js"}}}},
+            {{"request": {{"url": "{genshin_cn_url}"}}}},
+            {{"request": {{"url": "{star_rail_url}"}}}}
+        ]}}}}"#
+    );
+
+    let registry = GameRegistry::builtin();
+    let found = scan_for_urls(har.as_bytes(), &registry.games);
+    assert_eq!(
+        vec![(star_rail_url, "hkrpg_global"), (genshin_cn_url, "hk4e_cn")],
+        found
+            .iter()
+            .map(|found| (found.url.as_str(), found.game_type.game_biz().unwrap()))
+            .collect::<Vec<_>>()
+    );
+}
+
+#[test]
+fn get_data2_path() -> Result<()> {
+    let dir = tempdir()?;
+    let cache_data_dir = dir
+        .path()
+        .join("GenshinImpact_Data")
+        .join("webCaches")
+        .join("4.5.6.7")
+        .join("Cache")
+        .join("Cache_Data");
+    std::fs::create_dir_all(&cache_data_dir)?;
+    std::fs::File::create(cache_data_dir.join("data_2"))?;
+
+    assert_eq!(
+        1,
+        get_cache_dirs(&dir.path().join("GenshinImpact_Data").join("webCaches"))?.len()
+    );
+    Ok(())
+}
+
+#[test]
+fn get_data2_path_multiple_versions() -> Result<()> {
+    let dir = tempdir()?;
+    let older_cache = dir
+        .path()
+        .join("GenshinImpact_Data")
+        .join("webCaches")
+        .join("1.2.3.5000")
+        .join("Cache")
+        .join("Cache_Data");
+    std::fs::create_dir_all(&older_cache)?;
+    std::fs::File::create(older_cache.join("data_2"))?;
+
+    let newer_cache = dir
+        .path()
+        .join("GenshinImpact_Data")
+        .join("webCaches")
+        // Although the right most number is smaller, this is newer.
+        .join("1.2.4.0")
+        .join("Cache")
+        .join("Cache_Data");
+    std::fs::create_dir_all(&newer_cache)?;
+    std::fs::File::create(newer_cache.join("data_2"))?;
+
+    let cache_dirs = get_cache_dirs(&dir.path().join("GenshinImpact_Data").join("webCaches"))?;
+
+    assert_eq!(2, cache_dirs.len());
+    assert_eq!(cache_dirs[0].path, newer_cache);
+    assert_eq!(cache_dirs[1].path, older_cache);
+    Ok(())
+}
+
+#[test]
+fn get_data2_path_no_versioned_dir() -> Result<()> {
+    let dir = tempdir()?;
+    assert!(matches!(
+        get_cache_dirs(dir.path()),
+        Err(Error::NoVersionedCacheDir(_))
+    ));
+    Ok(())
+}
+
+#[test]
+fn get_data2_path_no_data2() -> Result<()> {
+    let dir = tempdir()?;
+    std::fs::create_dir_all(dir.path().join("4.5.6.7").join("Cache").join("Cache_Data"))?;
+    assert!(matches!(
+        get_cache_dirs(dir.path()),
+        Err(Error::NoCacheData(_))
+    ));
+    Ok(())
+}
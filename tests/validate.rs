@@ -0,0 +1,202 @@
+use anyhow::Result;
+use extract_wish_url::error::Remedy;
+use extract_wish_url::games::Validation;
+use extract_wish_url::validate::HttpClient;
+use extract_wish_url::validate::{test_gacha_log_api_url, test_genshin_wish_url, validate_all};
+use extract_wish_url::Error;
+use reqwest::blocking::Client;
+use reqwest::Url;
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+
+// Returns |body| for every request, and records the requested URLs.
+struct FakeClient {
+    body: String,
+    requests: Mutex<Vec<String>>,
+}
+
+impl HttpClient for FakeClient {
+    fn get(&self, url: &Url) -> Result<String, Error> {
+        self.requests.lock().unwrap().push(url.to_string());
+        Ok(self.body.clone())
+    }
+}
+
+#[test]
+fn test_zzz_url() -> Result<()> {
+    let mut server = mockito::Server::new();
+    let url= format!("{}{}{}",
+        "http://", 
+        &server.host_with_port(),
+        // Note that these include the required params.
+        "/index.html?extraparam=1234&authkey=key&authkey_ver=2&sign_type=sometype&game_biz=nap_global&lang=en&more=stuff&andsomemore=fluffs");
+
+    // Create a mock
+    let mock = server
+        // This path matches the above.
+        .mock("GET", "/index.html?extraparam=1234&authkey=key&authkey_ver=2&sign_type=sometype&game_biz=nap_global&lang=en&more=stuff&andsomemore=fluffs")
+        .with_status(200)
+        .with_header("content-type", "application/json")
+        // A minimal JSON to return retcode=0.
+        .with_body(r#"{"retcode": 0}"#)
+        .create();
+
+    let result = test_gacha_log_api_url(&Client::new(), &url)?;
+    // Verify that extraneous params are removed.
+    // Hardcoded 127.0.0.1 without a port number. Note that
+    // server.host_with_port() includes a port number.
+    // Its ok to change the host here if the framework changes.
+    assert_eq!("http://127.0.0.1/index.html?authkey=key&authkey_ver=2&sign_type=sometype&game_biz=nap_global&lang=en", result);
+    mock.assert();
+    Ok(())
+}
+
+#[test]
+fn test_genshin_url() -> Result<()> {
+    let mut server = mockito::Server::new();
+    let url= format!("{}{}{}",
+        "http://", 
+        &server.host_with_port(),
+        "/index.html?extraparam=1234&authkey=key&authkey_ver=2&sign_type=sometype&game_biz=hk4e_global&lang=en&more=stuff&andsomemore=fluffs");
+
+    let mock = server
+        // Verify that the parameters are added.
+        .mock("GET", "/gacha_info/api/getGachaLog?andsomemore=fluffs&authkey=key&authkey_ver=2&extraparam=1234&gacha_type=301&game_biz=hk4e_global&lang=en-us&more=stuff&sign_type=sometype&size=5")
+        .with_status(200)
+        .with_header("content-type", "application/json")
+        // A minimal JSON to return retcode=0.
+        .with_body(r#"{"retcode": 0}"#)
+        .create();
+
+    let query_overrides = BTreeMap::from([
+        ("gacha_type".to_string(), "301".to_string()),
+        ("size".to_string(), "5".to_string()),
+        ("lang".to_string(), "en-us".to_string()),
+    ]);
+    let result = test_genshin_wish_url(
+        &Client::new(),
+        &url,
+        &server.host_with_port(),
+        "gacha_info/api/getGachaLog",
+        &query_overrides,
+    )?;
+
+    // Verify that the original URL is returned.
+    assert_eq!(
+        format!("http://{}/index.html?extraparam=1234&authkey=key&authkey_ver=2&sign_type=sometype&game_biz=hk4e_global&lang=en&more=stuff&andsomemore=fluffs",
+        &server.host_with_port()),
+        result);
+    mock.assert();
+    Ok(())
+}
+
+#[test]
+fn test_zzz_url_retcode_not_0() {
+    let mut server = mockito::Server::new();
+    let url= format!("{}{}{}",
+        "http://", 
+        &server.host_with_port(),
+        // Note that these include the required params.
+        "/index.html?extraparam=1234&authkey=key&authkey_ver=2&sign_type=sometype&game_biz=nap_global&lang=en&more=stuff&andsomemore=fluffs");
+
+    // Create a mock
+    let mock = server
+        // This path matches the above.
+        .mock("GET", "/index.html?extraparam=1234&authkey=key&authkey_ver=2&sign_type=sometype&game_biz=nap_global&lang=en&more=stuff&andsomemore=fluffs")
+        .with_status(200)
+        .with_header("content-type", "application/json")
+        // Retcode is -1! The function should return an error.
+        .with_body(r#"{"retcode": -1}"#)
+        .create();
+
+    let result = test_gacha_log_api_url(&Client::new(), &url);
+    assert!(matches!(result, Err(Error::Api { retcode: -1, .. })));
+    mock.assert();
+}
+
+#[test]
+fn test_zzz_url_authkey_expired() {
+    let mut server = mockito::Server::new();
+    let url = format!(
+        "http://{}/getGachaLog?authkey=key&game_biz=nap_global",
+        &server.host_with_port()
+    );
+    let mock = server
+        .mock("GET", "/getGachaLog?authkey=key&game_biz=nap_global")
+        .with_status(200)
+        .with_body(r#"{"retcode": -101, "message": "authkey timeout"}"#)
+        .create();
+
+    let result = test_gacha_log_api_url(&Client::new(), &url);
+    match result {
+        Err(e @ Error::AuthkeyExpired { .. }) => {
+            assert_eq!("The authkey has expired: authkey timeout", e.to_string());
+            assert_eq!(Some(Remedy::OpenGachaHistory), e.remedy());
+        }
+        other => panic!("Unexpected result {:?}", other),
+    }
+    mock.assert();
+}
+
+#[test]
+fn test_star_rail_url_with_fake_client() -> Result<()> {
+    let client = FakeClient {
+        body: r#"{"retcode": 0}"#.to_string(),
+        requests: Mutex::new(vec![]),
+    };
+    let url = "https://public-operation-hkrpg-sg.hoyoverse.com/common/gacha_record/api/getGachaLog?authkey=key&extra=1&game_biz=hkrpg_global";
+
+    let result = test_gacha_log_api_url(&client, url)?;
+    assert_eq!("https://public-operation-hkrpg-sg.hoyoverse.com/common/gacha_record/api/getGachaLog?authkey=key&game_biz=hkrpg_global", result);
+    // The URL is requested as is, over HTTPS.
+    assert_eq!(vec![url.to_string()], *client.requests.lock().unwrap());
+    Ok(())
+}
+
+#[test]
+fn test_url_retcodes() {
+    for (retcode, message, remedy) in [
+        (-100, "authkey error", Some(Remedy::OpenGachaHistory)),
+        (-110, "visit too frequently", Some(Remedy::RetryLater)),
+        (-111, "game name error", Some(Remedy::CheckRegion)),
+        (-1, "system error", None),
+    ] {
+        let body = format!(r#"{{"retcode": {}, "message": "{}"}}"#, retcode, message);
+        let client = FakeClient {
+            body,
+            requests: Mutex::new(vec![]),
+        };
+        let error =
+            test_gacha_log_api_url(&client, "https://a.com/getGachaLog?authkey=key").unwrap_err();
+        assert_eq!(remedy, error.remedy(), "retcode {}", retcode);
+        // The message from the API is shown to the user.
+        assert!(error.to_string().ends_with(message), "{}", error);
+    }
+}
+
+// The results are in the order of the URLs, whichever request finishes first.
+#[test]
+fn test_validate_all() {
+    let client = FakeClient {
+        body: r#"{"retcode": 0}"#.to_string(),
+        requests: Mutex::new(vec![]),
+    };
+    let urls = (0..10)
+        .map(|i| format!("https://a.com/getGachaLog?authkey=key{}", i))
+        .collect::<Vec<_>>();
+    let validation = Validation::GachaLogApi;
+    let urls_to_validate = urls
+        .iter()
+        .map(|url| (&validation, url.as_str()))
+        .collect::<Vec<_>>();
+
+    let results = validate_all(&client, &urls_to_validate, 3);
+    assert_eq!(
+        urls,
+        results
+            .into_iter()
+            .map(|result| result.unwrap())
+            .collect::<Vec<_>>()
+    );
+    assert_eq!(10, client.requests.lock().unwrap().len());
+}